@@ -0,0 +1,273 @@
+//! Automatic codec selection, for callers who would rather not guess which packing suits their data.
+//!
+//! `encode_auto` encodes the bytes with every built-in strategy and keeps the shortest result (the earliest
+//! strategy wins a tie), with the strategy's id in a leading header word so that `decode_auto` knows how to
+//! unpack the rest.
+use crate::huffman::{self, HuffmanError};
+use crate::lz::{self, LzError};
+use crate::rle;
+use crate::s_d_bits_i32::{self, BitsError};
+use crate::s_d_u8_i32;
+use crate::validate::first_invalid_word;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    // serialize_u8_to_i32 as it is
+    Plain,
+    // serialize_u8_to_i32_rle
+    RunLength,
+    // Every bit of every byte packed with serialize_bits_to_i32, 31 bits per word
+    BitDense,
+    // lz::compress and then serialize_u8_to_i32
+    Lz,
+    // encode_huffman
+    Huffman,
+}
+
+pub const STRATEGIES: [Strategy; 5] = [
+    Strategy::Plain,
+    Strategy::RunLength,
+    Strategy::BitDense,
+    Strategy::Lz,
+    Strategy::Huffman,
+];
+
+impl Strategy {
+    pub fn id(&self) -> i32 {
+        match self {
+            Strategy::Plain => 0,
+            Strategy::RunLength => 1,
+            Strategy::BitDense => 2,
+            Strategy::Lz => 3,
+            Strategy::Huffman => 4,
+        }
+    }
+
+    pub fn from_id(id: i32) -> Option<Strategy> {
+        STRATEGIES
+            .iter()
+            .cloned()
+            .find(|strategy| strategy.id() == id)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AutoError {
+    MissingHeader,
+    UnknownStrategy(i32),
+    // A payload word the strategy's decoder can not decode, by its index in the encoded vector
+    InvalidWord { index: usize },
+    // A bit dense payload with fewer words than its bit count needs
+    Truncated,
+    // A bit dense payload which does not hold a whole number of bytes
+    PartialByte,
+    Lz(LzError),
+    Huffman(HuffmanError),
+}
+
+impl fmt::Display for AutoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AutoError::MissingHeader => write!(f, "missing strategy header word"),
+            AutoError::UnknownStrategy(id) => write!(f, "unknown strategy {}", id),
+            AutoError::InvalidWord { index } => write!(f, "word {} can not be decoded", index),
+            AutoError::Truncated => write!(f, "bit dense payload is truncated"),
+            AutoError::PartialByte => write!(f, "bit dense payload ends in a partial byte"),
+            AutoError::Lz(error) => write!(f, "{}", error),
+            AutoError::Huffman(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for AutoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AutoError::Lz(error) => Some(error),
+            AutoError::Huffman(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<LzError> for AutoError {
+    fn from(error: LzError) -> AutoError {
+        AutoError::Lz(error)
+    }
+}
+
+impl From<HuffmanError> for AutoError {
+    fn from(error: HuffmanError) -> AutoError {
+        AutoError::Huffman(error)
+    }
+}
+
+// The payload for one strategy, without the header word
+fn encode_payload(u8_data: &[u8], strategy: Strategy) -> Vec<i32> {
+    match strategy {
+        Strategy::Plain => s_d_u8_i32::serialize_u8_to_i32(u8_data.to_vec()),
+        Strategy::RunLength => rle::serialize_u8_to_i32_rle(u8_data.to_vec()),
+        Strategy::BitDense => s_d_bits_i32::serialize_bits_to_i32(
+            u8_data
+                .iter()
+                .flat_map(|byte| (0..8).map(move |bit| byte >> bit & 1 == 1))
+                .collect(),
+        ),
+        Strategy::Lz => s_d_u8_i32::serialize_u8_to_i32(lz::compress(u8_data)),
+        Strategy::Huffman => huffman::encode_huffman(u8_data.to_vec()),
+    }
+}
+
+pub fn encode_with(u8_data: &[u8], strategy: Strategy) -> Vec<i32> {
+    let mut vec_of_i32s: Vec<i32> = vec![strategy.id()];
+    vec_of_i32s.extend(encode_payload(u8_data, strategy));
+    vec_of_i32s
+}
+
+pub fn encode_auto(u8_data: &[u8]) -> Vec<i32> {
+    STRATEGIES
+        .iter()
+        .map(|strategy| encode_with(u8_data, *strategy))
+        .min_by_key(|i32_data| i32_data.len())
+        .unwrap()
+}
+
+// The strategy recorded in an encoded vector's header
+pub fn strategy_of(i32_data: &[i32]) -> Result<Strategy, AutoError> {
+    let id: i32 = *i32_data.first().ok_or(AutoError::MissingHeader)?;
+    Strategy::from_id(id).ok_or(AutoError::UnknownStrategy(id))
+}
+
+pub fn decode_auto(i32_data: &[i32]) -> Result<Vec<u8>, AutoError> {
+    let strategy: Strategy = strategy_of(i32_data)?;
+    let payload: Vec<i32> = i32_data[1..].to_vec();
+    // Bit dense and Huffman payloads are checked by their decoders, the others panic on words they can not decode
+    let invalid: Option<usize> = match strategy {
+        Strategy::Plain | Strategy::Lz => first_invalid_word(&payload),
+        Strategy::RunLength => rle::first_invalid_rle_word(&payload),
+        Strategy::BitDense | Strategy::Huffman => None,
+    };
+    if let Some(index) = invalid {
+        return Err(AutoError::InvalidWord { index: index + 1 });
+    }
+    match strategy {
+        Strategy::Plain => Ok(s_d_u8_i32::deserialize_i32_to_u8(payload)),
+        Strategy::RunLength => Ok(rle::deserialize_i32_to_u8_rle(payload)),
+        Strategy::BitDense => {
+            let bit_data: Vec<bool> = match s_d_bits_i32::deserialize_i32_to_bits_checked(&payload)
+            {
+                Ok(bit_data) => bit_data,
+                Err(BitsError::NegativeBitCount(_)) => {
+                    return Err(AutoError::InvalidWord { index: 1 })
+                }
+                Err(_) => return Err(AutoError::Truncated),
+            };
+            if !bit_data.len().is_multiple_of(8) {
+                return Err(AutoError::PartialByte);
+            }
+            Ok(bit_data
+                .chunks(8)
+                .map(|bits| {
+                    bits.iter()
+                        .rev()
+                        .fold(0, |byte, bit| byte << 1 | u8::from(*bit))
+                })
+                .collect())
+        }
+        Strategy::Lz => Ok(lz::decompress(&s_d_u8_i32::deserialize_i32_to_u8(payload))?),
+        Strategy::Huffman => Ok(huffman::decode_huffman(payload)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    fn noise(length: usize) -> Vec<u8> {
+        XorShift::new(88172645).bytes(length)
+    }
+
+    #[test]
+    fn test_picks_smallest() {
+        assert_eq!(strategy_of(&encode_auto(&[9, 10, 11])), Ok(Strategy::Plain));
+        assert_eq!(
+            strategy_of(&encode_auto(&[0; 1000])),
+            Ok(Strategy::RunLength)
+        );
+        assert_eq!(
+            strategy_of(&encode_auto(&noise(1000))),
+            Ok(Strategy::BitDense)
+        );
+        let document: Vec<u8> = b"{\"id\": 1, \"name\": \"sensor\"}\n".repeat(50);
+        assert_eq!(strategy_of(&encode_auto(&document)), Ok(Strategy::Lz));
+        // Few distinct bytes but no long repeats, as in a DNA sequence
+        let text: Vec<u8> = noise(1000)
+            .iter()
+            .map(|byte| b"acgt"[*byte as usize % 4])
+            .collect();
+        assert_eq!(strategy_of(&encode_auto(&text)), Ok(Strategy::Huffman));
+    }
+
+    #[test]
+    fn test_never_larger_than_plain() {
+        for length in 0..50 {
+            let data: Vec<u8> = noise(length);
+            assert!(encode_auto(&data).len() <= 1 + s_d_u8_i32::serialize_u8_to_i32(data).len());
+        }
+    }
+
+    #[test]
+    fn test_round_trip_every_strategy() {
+        let mut samples: Vec<Vec<u8>> = vec![Vec::new(), vec![7; 100], noise(77)];
+        samples.push(b"the rain in spain stays mainly in the plain".to_vec());
+        for data in samples {
+            for strategy in STRATEGIES.iter() {
+                let v: Vec<i32> = encode_with(&data, *strategy);
+                assert_eq!(v[0], strategy.id());
+                assert_eq!(decode_auto(&v).unwrap(), data);
+            }
+            assert_eq!(decode_auto(&encode_auto(&data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_bad_header() {
+        assert_eq!(decode_auto(&[]), Err(AutoError::MissingHeader));
+        assert_eq!(decode_auto(&[9, 1]), Err(AutoError::UnknownStrategy(9)));
+        assert_eq!(decode_auto(&[2, 3, 5]), Err(AutoError::PartialByte));
+        assert_eq!(decode_auto(&[2]), Err(AutoError::Truncated));
+        // 1000 bits need 33 words, not 8
+        assert_eq!(
+            decode_auto(&[2, 1000, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(AutoError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_invalid_words() {
+        for header in [0, 3] {
+            assert_eq!(
+                decode_auto(&[header, 1999999999]),
+                Err(AutoError::InvalidWord { index: 1 })
+            );
+        }
+        assert_eq!(
+            decode_auto(&[1, -40, 1999999999]),
+            Err(AutoError::InvalidWord { index: 2 })
+        );
+        assert_eq!(
+            decode_auto(&[1, -40999]),
+            Err(AutoError::InvalidWord { index: 1 })
+        );
+        assert_eq!(
+            decode_auto(&[2, -8, 1]),
+            Err(AutoError::InvalidWord { index: 1 })
+        );
+        assert!(matches!(
+            decode_auto(&[4, 1, 1, 1999999999, 1]),
+            Err(AutoError::Huffman(_))
+        ));
+    }
+}
@@ -0,0 +1,42 @@
+//! A dependency free checksum which always fits in a non-negative i32, so it can sit next to encoded data.
+//!
+//! This is Adler-32 with the top bit cleared.
+
+const MOD_ADLER: u32 = 65521;
+
+pub fn adler32(u8_data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    // 5552 is the largest run which can not overflow the u32 sums before the modulo is applied
+    for chunk in u8_data.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+pub fn checksum_u8(u8_data: &[u8]) -> i32 {
+    (adler32(u8_data) & 0x7fff_ffff) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_checksum_u8_is_non_negative() {
+        let data: Vec<u8> = vec![255; 16];
+        assert!(adler32(&data) > i32::MAX as u32);
+        assert!(checksum_u8(&data) >= 0);
+    }
+}
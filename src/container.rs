@@ -0,0 +1,217 @@
+//! Holding several named byte blobs (pixels, metadata JSON, a thumbnail etc.) in a single i32 vector.
+//!
+//! The vector starts with a table of contents: the number of entries, then for each entry the byte length of its
+//! name, the name itself packed with `serialize_u8_to_i32`, the entry's offset in words from the end of the table
+//! of contents and its byte length. Each blob follows, packed with `serialize_u8_to_i32` on its own so that a
+//! single entry can be decoded without touching the rest.
+use crate::s_d_u8_i32;
+use crate::validate::first_invalid_word;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerEntry {
+    pub name: String,
+    // Offset in words from the start of the data section
+    pub offset: usize,
+    // Length in bytes
+    pub length: usize,
+}
+
+impl ContainerEntry {
+    // The number of words the entry's data occupies
+    pub fn word_count(&self) -> usize {
+        self.length.div_ceil(3)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContainerError {
+    Truncated,
+    MalformedTableOfContents { index: usize },
+    InvalidName { index: usize },
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContainerError::Truncated => write!(f, "container is truncated"),
+            ContainerError::MalformedTableOfContents { index } => {
+                write!(f, "malformed table of contents entry {}", index)
+            }
+            ContainerError::InvalidName { index } => {
+                write!(f, "name of entry {} is not valid UTF-8", index)
+            }
+        }
+    }
+}
+
+impl Error for ContainerError {}
+
+pub fn encode_container(entries: &[(&str, &[u8])]) -> Vec<i32> {
+    let mut names: HashSet<&str> = HashSet::new();
+    let mut table_of_contents: Vec<i32> = vec![entries.len().try_into().unwrap()];
+    let mut data: Vec<i32> = Vec::new();
+    for (name, u8_data) in entries {
+        assert!(names.insert(name), "duplicate container entry {:?}", name);
+        table_of_contents.push(name.len().try_into().unwrap());
+        table_of_contents.extend(s_d_u8_i32::serialize_u8_to_i32(name.as_bytes().to_vec()));
+        table_of_contents.push(data.len().try_into().unwrap());
+        table_of_contents.push(u8_data.len().try_into().unwrap());
+        data.extend(s_d_u8_i32::serialize_u8_to_i32(u8_data.to_vec()));
+    }
+    table_of_contents.extend(data);
+    table_of_contents
+}
+
+// Reads the table of contents, returning the entries and the index of the first data word
+fn read_table_of_contents(
+    i32_data: &[i32],
+) -> Result<(Vec<ContainerEntry>, usize), ContainerError> {
+    let count: usize = (*i32_data.first().ok_or(ContainerError::Truncated)?)
+        .try_into()
+        .map_err(|_| ContainerError::MalformedTableOfContents { index: 0 })?;
+    let mut entries: Vec<ContainerEntry> = Vec::new();
+    let mut position: usize = 1;
+    for index in 0..count {
+        let malformed = ContainerError::MalformedTableOfContents { index };
+        let name_length: usize = (*i32_data.get(position).ok_or(ContainerError::Truncated)?)
+            .try_into()
+            .map_err(|_| malformed.clone())?;
+        let name_words: usize = name_length.div_ceil(3);
+        let name_end: usize = position + 1 + name_words;
+        let name_data: &[i32] = i32_data
+            .get(position + 1..name_end)
+            .ok_or(ContainerError::Truncated)?;
+        if first_invalid_word(name_data).is_some() {
+            return Err(malformed);
+        }
+        let name_bytes: Vec<u8> = s_d_u8_i32::deserialize_i32_to_u8(name_data.to_vec());
+        if name_bytes.len() != name_length {
+            return Err(malformed);
+        }
+        let name: String =
+            String::from_utf8(name_bytes).map_err(|_| ContainerError::InvalidName { index })?;
+        let offset_and_length: &[i32] = i32_data
+            .get(name_end..name_end + 2)
+            .ok_or(ContainerError::Truncated)?;
+        entries.push(ContainerEntry {
+            name,
+            offset: offset_and_length[0]
+                .try_into()
+                .map_err(|_| malformed.clone())?,
+            length: offset_and_length[1].try_into().map_err(|_| malformed)?,
+        });
+        position = name_end + 2;
+    }
+    Ok((entries, position))
+}
+
+pub fn list_entries(i32_data: &[i32]) -> Result<Vec<ContainerEntry>, ContainerError> {
+    Ok(read_table_of_contents(i32_data)?.0)
+}
+
+// Decodes only the named entry, returning None if the container has no such entry
+pub fn decode_entry(i32_data: &[i32], name: &str) -> Result<Option<Vec<u8>>, ContainerError> {
+    let (entries, data_start) = read_table_of_contents(i32_data)?;
+    match entries.iter().position(|entry| entry.name == name) {
+        Some(index) => {
+            let entry: &ContainerEntry = &entries[index];
+            let start: usize = data_start
+                .checked_add(entry.offset)
+                .ok_or(ContainerError::Truncated)?;
+            let entry_data: &[i32] = i32_data
+                .get(start..start + entry.word_count())
+                .ok_or(ContainerError::Truncated)?;
+            let malformed = ContainerError::MalformedTableOfContents { index };
+            if first_invalid_word(entry_data).is_some() {
+                return Err(malformed);
+            }
+            let u8_data: Vec<u8> = s_d_u8_i32::deserialize_i32_to_u8(entry_data.to_vec());
+            // A tail word in the middle of the entry decodes to fewer bytes than the table of contents says
+            if u8_data.len() != entry.length {
+                return Err(malformed);
+            }
+            Ok(Some(u8_data))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_container() {
+        let v: Vec<i32> = encode_container(&[("px", &[1, 2, 3, 4]), ("meta", b"{}")]);
+        assert_eq!(
+            v,
+            vec![2, 2, 2000112120, 0, 4, 4, 1109101116, 97, 2, 2, 1001002003, 4, 2000123125]
+        );
+    }
+
+    #[test]
+    fn test_list_and_decode_entries() {
+        let pixels: Vec<u8> = (0..100).collect();
+        let metadata: &[u8] = br#"{"width":10,"height":10}"#;
+        let v: Vec<i32> = encode_container(&[
+            ("pixels", &pixels),
+            ("metadata.json", metadata),
+            ("empty", &[]),
+        ]);
+        let entries: Vec<ContainerEntry> = list_entries(&v).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["pixels", "metadata.json", "empty"]);
+        assert_eq!(entries[1].offset, 34);
+        assert_eq!(entries[1].length, metadata.len());
+        assert_eq!(decode_entry(&v, "pixels").unwrap(), Some(pixels));
+        assert_eq!(
+            decode_entry(&v, "metadata.json").unwrap(),
+            Some(metadata.to_vec())
+        );
+        assert_eq!(decode_entry(&v, "empty").unwrap(), Some(Vec::new()));
+        assert_eq!(decode_entry(&v, "thumbnail").unwrap(), None);
+    }
+
+    #[test]
+    fn test_truncated_container() {
+        let v: Vec<i32> = encode_container(&[("a", &[1, 2, 3, 4, 5])]);
+        assert_eq!(list_entries(&v[..2]), Err(ContainerError::Truncated));
+        assert_eq!(
+            decode_entry(&v[..v.len() - 1], "a"),
+            Err(ContainerError::Truncated)
+        );
+        assert_eq!(list_entries(&[]), Err(ContainerError::Truncated));
+    }
+
+    #[test]
+    fn test_corrupt_words() {
+        assert_eq!(
+            list_entries(&[1, 1, 1999999999, 0, 0]),
+            Err(ContainerError::MalformedTableOfContents { index: 0 })
+        );
+        let mut v: Vec<i32> = encode_container(&[("a", &[1, 2, 3, 4, 5]), ("b", &[6])]);
+        // The first data word of entry "a"
+        v[9] = 1999999999;
+        assert_eq!(
+            decode_entry(&v, "a"),
+            Err(ContainerError::MalformedTableOfContents { index: 0 })
+        );
+        assert_eq!(decode_entry(&v, "b").unwrap(), Some(vec![6]));
+        // A tail word in place of a full one leaves entry "a" short
+        v[9] = 2000001002;
+        assert_eq!(
+            decode_entry(&v, "a"),
+            Err(ContainerError::MalformedTableOfContents { index: 0 })
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_duplicate_names() {
+        encode_container(&[("a", &[1]), ("a", &[2])]);
+    }
+}
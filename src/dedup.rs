@@ -0,0 +1,459 @@
+//! Content addressed deduplication of stored bytes.
+//!
+//! A blob is split into chunks (either of a fixed size or at content defined boundaries), each unique chunk is
+//! stored once as an ordinary `serialize_u8_to_i32` vector, and the blob itself is stored as a recipe vector which
+//! starts with the number of chunks, followed by the storage key and byte length of each chunk i.e. `[2, 7, 4096, 9, 100]`.
+//! The store keeps an index vector listing the keys of all its chunks in the storage too, so that a store opened
+//! again later still deduplicates against (and garbage collects) the chunks written before.
+use crate::s_d_u8_i32;
+use crate::storage::Storage;
+use crate::validate::first_invalid_word;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chunking {
+    // Every chunk has the same size, apart from the last one
+    Fixed(usize),
+    // Boundaries are placed with a rolling hash so that an insertion only changes the chunks around it
+    ContentDefined {
+        min_size: usize,
+        average_size: usize,
+        max_size: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DedupError {
+    MalformedRecipe,
+    // The index lists a chunk which is missing from the storage
+    MissingChunk {
+        key: i32,
+    },
+    // A stored chunk with a word deserialize_i32_to_u8 can not decode
+    InvalidChunk {
+        key: i32,
+    },
+    ChunkLengthMismatch {
+        key: i32,
+        expected: i32,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for DedupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DedupError::MalformedRecipe => write!(f, "malformed blob recipe"),
+            DedupError::MissingChunk { key } => write!(f, "indexed chunk {} is missing", key),
+            DedupError::InvalidChunk { key } => write!(f, "chunk {} can not be decoded", key),
+            DedupError::ChunkLengthMismatch {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "chunk {} holds {} bytes, the recipe expects {}",
+                key, actual, expected
+            ),
+        }
+    }
+}
+
+impl Error for DedupError {}
+
+// FNV-1a, only used to find candidate chunks so collisions are checked against the stored bytes
+fn hash_chunk(u8_data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in u8_data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+// A fixed table of pseudo random values (from splitmix64) for the gear rolling hash
+const fn gear_table() -> [u64; 256] {
+    let mut table: [u64; 256] = [0; 256];
+    let mut state: u64 = 0;
+    let mut i: usize = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z: u64 = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+const GEAR: [u64; 256] = gear_table();
+
+pub fn split_into_chunks(u8_data: &[u8], chunking: Chunking) -> Vec<&[u8]> {
+    match chunking {
+        Chunking::Fixed(size) => {
+            assert!(size > 0, "chunk size must be at least 1 byte");
+            u8_data.chunks(size).collect()
+        }
+        Chunking::ContentDefined {
+            min_size,
+            average_size,
+            max_size,
+        } => {
+            assert!(
+                min_size > 0 && min_size <= max_size,
+                "chunk sizes must satisfy 0 < min_size <= max_size"
+            );
+            let mask: u64 = average_size.max(1).next_power_of_two() as u64 - 1;
+            let mut chunks: Vec<&[u8]> = Vec::new();
+            let mut start: usize = 0;
+            let mut hash: u64 = 0;
+            for (i, byte) in u8_data.iter().enumerate() {
+                hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+                let length: usize = i + 1 - start;
+                if (length >= min_size && hash & mask == 0) || length >= max_size {
+                    chunks.push(&u8_data[start..=i]);
+                    start = i + 1;
+                    hash = 0;
+                }
+            }
+            if start < u8_data.len() {
+                chunks.push(&u8_data[start..]);
+            }
+            chunks
+        }
+    }
+}
+
+pub struct ChunkStore<S: Storage> {
+    storage: S,
+    chunking: Chunking,
+    // Storage key of the index of chunk keys
+    index_key: i32,
+    // Content hash to the storage keys of the chunks with that hash
+    chunks_by_hash: HashMap<u64, Vec<i32>>,
+    // Storage key to content hash, for every chunk this store has written
+    chunk_hashes: HashMap<i32, u64>,
+}
+
+impl<S: Storage> ChunkStore<S> {
+    // Starts a new store, writing an empty index to the storage
+    pub fn new(mut storage: S, chunking: Chunking) -> ChunkStore<S> {
+        let index_key: i32 = storage.store(&[]);
+        ChunkStore {
+            storage,
+            chunking,
+            index_key,
+            chunks_by_hash: HashMap::new(),
+            chunk_hashes: HashMap::new(),
+        }
+    }
+
+    // Opens a store created earlier, rebuilding the content hashes from the chunks its index lists
+    pub fn open(
+        storage: S,
+        chunking: Chunking,
+        index_key: i32,
+    ) -> Result<ChunkStore<S>, DedupError> {
+        let mut store = ChunkStore {
+            storage,
+            chunking,
+            index_key,
+            chunks_by_hash: HashMap::new(),
+            chunk_hashes: HashMap::new(),
+        };
+        for key in store.storage.load(index_key) {
+            // Chunks are never empty, so an empty vector means the chunk is gone
+            let chunk: Vec<u8> = store.load_chunk(key)?;
+            if chunk.is_empty() {
+                return Err(DedupError::MissingChunk { key });
+            }
+            let hash: u64 = hash_chunk(&chunk);
+            store.chunks_by_hash.entry(hash).or_default().push(key);
+            store.chunk_hashes.insert(key, hash);
+        }
+        Ok(store)
+    }
+
+    // The key to pass to open to get this store back
+    pub fn index_key(&self) -> i32 {
+        self.index_key
+    }
+
+    fn write_index(&mut self) {
+        let mut keys: Vec<i32> = self.chunk_hashes.keys().cloned().collect();
+        keys.sort_unstable();
+        self.storage.update(self.index_key, &keys);
+    }
+
+    fn load_chunk(&self, key: i32) -> Result<Vec<u8>, DedupError> {
+        let chunk: Vec<i32> = self.storage.load(key);
+        if first_invalid_word(&chunk).is_some() {
+            return Err(DedupError::InvalidChunk { key });
+        }
+        Ok(s_d_u8_i32::deserialize_i32_to_u8(chunk))
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+
+    // The number of unique chunks currently stored
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_hashes.len()
+    }
+
+    // Returns the key of an identical chunk which is already stored, or stores this one. A corrupt stored chunk
+    // never matches, so the bytes get stored afresh
+    fn store_chunk(&mut self, chunk: &[u8]) -> i32 {
+        let hash: u64 = hash_chunk(chunk);
+        if let Some(keys) = self.chunks_by_hash.get(&hash) {
+            for key in keys {
+                if self.load_chunk(*key).as_deref() == Ok(chunk) {
+                    return *key;
+                }
+            }
+        }
+        let key: i32 = self
+            .storage
+            .store(&s_d_u8_i32::serialize_u8_to_i32(chunk.to_vec()));
+        self.chunks_by_hash.entry(hash).or_default().push(key);
+        self.chunk_hashes.insert(key, hash);
+        key
+    }
+
+    // Stores the blob and returns the key of its recipe
+    pub fn store_blob(&mut self, u8_data: &[u8]) -> i32 {
+        let chunks: Vec<&[u8]> = split_into_chunks(u8_data, self.chunking);
+        let chunk_count: usize = self.chunk_count();
+        let mut recipe: Vec<i32> = vec![chunks.len().try_into().unwrap()];
+        for chunk in chunks {
+            recipe.push(self.store_chunk(chunk));
+            recipe.push(chunk.len().try_into().unwrap());
+        }
+        if self.chunk_count() != chunk_count {
+            self.write_index();
+        }
+        self.storage.store(&recipe)
+    }
+
+    // The storage keys of the chunks a blob is made of, in order
+    pub fn chunk_keys(&self, blob_key: i32) -> Result<Vec<i32>, DedupError> {
+        Ok(self
+            .decode_recipe(blob_key)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    fn decode_recipe(&self, blob_key: i32) -> Result<Vec<(i32, i32)>, DedupError> {
+        let recipe: Vec<i32> = self.storage.load(blob_key);
+        let (count, entries) = recipe.split_first().ok_or(DedupError::MalformedRecipe)?;
+        if *count < 0 || entries.len() != *count as usize * 2 {
+            return Err(DedupError::MalformedRecipe);
+        }
+        Ok(entries
+            .chunks(2)
+            .map(|entry| (entry[0], entry[1]))
+            .collect())
+    }
+
+    pub fn load_blob(&self, blob_key: i32) -> Result<Vec<u8>, DedupError> {
+        let mut u8_data: Vec<u8> = Vec::new();
+        for (key, length) in self.decode_recipe(blob_key)? {
+            let chunk: Vec<u8> = self.load_chunk(key)?;
+            if length < 0 || chunk.len() != length as usize {
+                return Err(DedupError::ChunkLengthMismatch {
+                    key,
+                    expected: length,
+                    actual: chunk.len(),
+                });
+            }
+            u8_data.extend(chunk);
+        }
+        Ok(u8_data)
+    }
+
+    // Removes the blob's recipe, its chunks stay until the next garbage collection
+    pub fn delete_blob(&mut self, blob_key: i32) {
+        self.storage.delete(blob_key);
+    }
+
+    // Deletes every chunk which is not referenced by one of the live blobs, returning how many were removed
+    pub fn collect_garbage(&mut self, live_blob_keys: &[i32]) -> Result<usize, DedupError> {
+        let mut referenced: HashSet<i32> = HashSet::new();
+        for blob_key in live_blob_keys {
+            referenced.extend(self.chunk_keys(*blob_key)?);
+        }
+        let unreferenced: Vec<(i32, u64)> = self
+            .chunk_hashes
+            .iter()
+            .filter(|(key, _)| !referenced.contains(key))
+            .map(|(key, hash)| (*key, *hash))
+            .collect();
+        for (key, hash) in &unreferenced {
+            self.storage.delete(*key);
+            self.chunk_hashes.remove(key);
+            if let Some(keys) = self.chunks_by_hash.get_mut(hash) {
+                keys.retain(|k| k != key);
+                if keys.is_empty() {
+                    self.chunks_by_hash.remove(hash);
+                }
+            }
+        }
+        if !unreferenced.is_empty() {
+            self.write_index();
+        }
+        Ok(unreferenced.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_util::XorShift;
+
+    fn test_data(length: usize, seed: u64) -> Vec<u8> {
+        XorShift::new(seed).bytes(length)
+    }
+
+    #[test]
+    fn test_identical_tiles_are_stored_once() {
+        let mut store = ChunkStore::new(MemoryStorage::new(), Chunking::Fixed(16));
+        let tile: Vec<u8> = test_data(16, 1);
+        let image: Vec<u8> = tile.repeat(10);
+        let key: i32 = store.store_blob(&image);
+        assert_eq!(store.chunk_count(), 1);
+        assert_eq!(store.load_blob(key).unwrap(), image);
+        // A second blob sharing the tile only adds its own unique chunk
+        let mut other: Vec<u8> = tile.clone();
+        other.extend(test_data(5, 2));
+        let other_key: i32 = store.store_blob(&other);
+        assert_eq!(store.chunk_count(), 2);
+        assert_eq!(store.load_blob(other_key).unwrap(), other);
+    }
+
+    #[test]
+    fn test_content_defined_chunks_survive_an_insertion() {
+        let chunking = Chunking::ContentDefined {
+            min_size: 32,
+            average_size: 128,
+            max_size: 1024,
+        };
+        let mut store = ChunkStore::new(MemoryStorage::new(), chunking);
+        let data: Vec<u8> = test_data(20_000, 3);
+        let key: i32 = store.store_blob(&data);
+        let before: usize = store.chunk_count();
+        let mut shifted: Vec<u8> = vec![42];
+        shifted.extend(&data);
+        let shifted_key: i32 = store.store_blob(&shifted);
+        assert_eq!(store.load_blob(key).unwrap(), data);
+        assert_eq!(store.load_blob(shifted_key).unwrap(), shifted);
+        // Only the chunk around the insertion point should be new
+        assert!(store.chunk_count() - before <= 2);
+        // Every chunk but the last respects the size limits
+        let chunks: Vec<&[u8]> = split_into_chunks(&data, chunking);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.len() >= 32 && chunk.len() <= 1024);
+        }
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let mut store = ChunkStore::new(MemoryStorage::new(), Chunking::Fixed(8));
+        let shared: Vec<u8> = test_data(8, 4);
+        let mut first: Vec<u8> = shared.clone();
+        first.extend(test_data(8, 5));
+        let mut second: Vec<u8> = shared.clone();
+        second.extend(test_data(8, 6));
+        let first_key: i32 = store.store_blob(&first);
+        let second_key: i32 = store.store_blob(&second);
+        assert_eq!(store.chunk_count(), 3);
+        store.delete_blob(first_key);
+        assert_eq!(store.collect_garbage(&[second_key]).unwrap(), 1);
+        assert_eq!(store.chunk_count(), 2);
+        assert_eq!(store.load_blob(second_key).unwrap(), second);
+        // Storing the first blob again re-creates only the collected chunk
+        store.store_blob(&first);
+        assert_eq!(store.chunk_count(), 3);
+        // The storage holds the chunks, the two live recipes and the index
+        assert_eq!(store.storage().len(), 6);
+    }
+
+    #[test]
+    fn test_reopened_store_keeps_deduplicating() {
+        let mut store = ChunkStore::new(MemoryStorage::new(), Chunking::Fixed(8));
+        let first: Vec<u8> = test_data(32, 7);
+        let first_key: i32 = store.store_blob(&first);
+        let second_key: i32 = store.store_blob(&test_data(16, 8));
+        let index_key: i32 = store.index_key();
+        let storage: MemoryStorage = store.into_storage();
+        let stored: usize = storage.len();
+        let mut reopened = ChunkStore::open(storage, Chunking::Fixed(8), index_key).unwrap();
+        assert_eq!(reopened.chunk_count(), 6);
+        // Only a new recipe is written, every chunk is already there
+        let again_key: i32 = reopened.store_blob(&first);
+        assert_eq!(reopened.storage().len(), stored + 1);
+        assert_eq!(reopened.load_blob(again_key).unwrap(), first);
+        // Chunks from the earlier session can be collected
+        reopened.delete_blob(first_key);
+        reopened.delete_blob(second_key);
+        assert_eq!(reopened.collect_garbage(&[again_key]).unwrap(), 2);
+        let index_key: i32 = reopened.index_key();
+        let storage: MemoryStorage = reopened.into_storage();
+        let reopened = ChunkStore::open(storage, Chunking::Fixed(8), index_key).unwrap();
+        assert_eq!(reopened.chunk_count(), 4);
+        assert_eq!(reopened.load_blob(again_key).unwrap(), first);
+    }
+
+    #[test]
+    fn test_open_with_missing_chunk() {
+        let mut store = ChunkStore::new(MemoryStorage::new(), Chunking::Fixed(8));
+        let blob_key: i32 = store.store_blob(&test_data(8, 9));
+        let chunk_key: i32 = store.chunk_keys(blob_key).unwrap()[0];
+        let index_key: i32 = store.index_key();
+        let mut storage: MemoryStorage = store.into_storage();
+        storage.delete(chunk_key);
+        assert_eq!(
+            ChunkStore::open(storage, Chunking::Fixed(8), index_key).err(),
+            Some(DedupError::MissingChunk { key: chunk_key })
+        );
+    }
+
+    #[test]
+    fn test_corrupt_chunk() {
+        let mut store = ChunkStore::new(MemoryStorage::new(), Chunking::Fixed(8));
+        let data: Vec<u8> = test_data(16, 10);
+        let blob_key: i32 = store.store_blob(&data);
+        let chunk_key: i32 = store.chunk_keys(blob_key).unwrap()[1];
+        store
+            .storage
+            .update(chunk_key, &[1999000000, 1000000000, 1000000000]);
+        let expected = Some(DedupError::InvalidChunk { key: chunk_key });
+        assert_eq!(store.load_blob(blob_key).err(), expected);
+        // Storing the same bytes again does not match the corrupt chunk
+        let again_key: i32 = store.store_blob(&data);
+        assert_eq!(store.load_blob(again_key).unwrap(), data);
+        let index_key: i32 = store.index_key();
+        assert_eq!(
+            ChunkStore::open(store.into_storage(), Chunking::Fixed(8), index_key).err(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_malformed_recipe() {
+        let mut storage = MemoryStorage::new();
+        let bad_key: i32 = storage.store(&[3, 1]);
+        let store = ChunkStore::new(storage, Chunking::Fixed(8));
+        assert_eq!(store.load_blob(bad_key), Err(DedupError::MalformedRecipe));
+    }
+}
@@ -0,0 +1,447 @@
+//! A growable buffer of `serialize_u8_to_i32` words which keeps itself well formed.
+//!
+//! Simply concatenating two encoded vectors goes wrong whenever the first one ends in a tail word, because the
+//! decoder would then find a partial word in the middle of the stream. `EncodedBytes` re-packs the words around the
+//! boundary instead, so the result is always exactly what `serialize_u8_to_i32` would produce for the same bytes.
+//!
+//! An `EncodedBytes` can only be created by the encoder or by validating an existing `Vec<i32>` with `TryFrom`,
+//! so a function taking one can rely on its words being well formed.
+use crate::s_d_u8_i32;
+use crate::validate::{validate, IssueReason};
+use crate::view::{byte_at, bytes_in_word, encoded_len, resolve_range, EncodedView};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{Range, RangeBounds};
+
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct EncodedBytes {
+    i32_data: Vec<i32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidEncoding {
+    // Index of the first word which serialize_u8_to_i32 could not have produced
+    pub index: usize,
+    pub word: i32,
+    pub reason: IssueReason,
+}
+
+impl fmt::Display for InvalidEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid encoded word {} at index {}: {}",
+            self.word, self.index, self.reason
+        )
+    }
+}
+
+impl Error for InvalidEncoding {}
+
+// Removes a trailing tail word (if there is one) and returns the bytes it held
+fn pop_tail(i32_data: &mut Vec<i32>) -> Vec<u8> {
+    match i32_data.last() {
+        Some(last) if bytes_in_word(*last) < 3 => {
+            let last: i32 = i32_data.pop().unwrap();
+            s_d_u8_i32::deserialize_i32_to_u8(vec![last])
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Replaces the bytes in range with the replacement bytes, directly on a well formed encoded vector.
+// The words before the one holding the start of the range are left untouched, only the rest is re-packed.
+pub fn splice<R: RangeBounds<usize>>(i32_data: &mut Vec<i32>, range: R, replacement: &[u8]) {
+    let range: Range<usize> = resolve_range(range, encoded_len(i32_data));
+    let view = EncodedView::new(i32_data);
+    let mut repacked: Vec<u8> = view.range(range.start / 3 * 3..range.start);
+    repacked.extend_from_slice(replacement);
+    repacked.extend(view.range(range.end..));
+    i32_data.truncate(range.start / 3);
+    i32_data.extend(s_d_u8_i32::serialize_u8_to_i32(repacked));
+}
+
+// Removes the bytes in range, directly on a well formed encoded vector
+pub fn remove<R: RangeBounds<usize>>(i32_data: &mut Vec<i32>, range: R) {
+    splice(i32_data, range, &[]);
+}
+
+impl EncodedBytes {
+    pub fn new() -> EncodedBytes {
+        EncodedBytes::default()
+    }
+
+    pub fn encode(u8_data: Vec<u8>) -> EncodedBytes {
+        EncodedBytes {
+            i32_data: s_d_u8_i32::serialize_u8_to_i32(u8_data),
+        }
+    }
+
+    pub fn decode(&self) -> Vec<u8> {
+        s_d_u8_i32::deserialize_i32_to_u8(self.i32_data.clone())
+    }
+
+    pub fn as_words(&self) -> &[i32] {
+        &self.i32_data
+    }
+
+    pub fn into_words(self) -> Vec<i32> {
+        self.i32_data
+    }
+
+    pub fn view(&self) -> EncodedView<'_> {
+        EncodedView::new(&self.i32_data)
+    }
+
+    // The number of bytes held
+    pub fn len(&self) -> usize {
+        encoded_len(&self.i32_data)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.i32_data.is_empty()
+    }
+
+    pub fn extend_from_slice(&mut self, u8_data: &[u8]) {
+        if u8_data.is_empty() {
+            return;
+        }
+        // A tail word can not stay in the middle, so its bytes are packed again together with the new ones
+        let mut tail: Vec<u8> = pop_tail(&mut self.i32_data);
+        tail.extend_from_slice(u8_data);
+        self.i32_data.extend(s_d_u8_i32::serialize_u8_to_i32(tail));
+    }
+
+    // Moves all of other's bytes onto the end of self, leaving other empty
+    pub fn append(&mut self, other: &mut EncodedBytes) {
+        let other_words: Vec<i32> = std::mem::take(&mut other.i32_data);
+        match self.i32_data.last() {
+            Some(last) if bytes_in_word(*last) < 3 => {
+                self.extend_from_slice(&s_d_u8_i32::deserialize_i32_to_u8(other_words))
+            }
+            // Only full words so far, the other buffer's words can be taken as they are
+            _ => self.i32_data.extend(other_words),
+        }
+    }
+
+    // Shortens the buffer to len bytes, doing nothing if it is already that short
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len() {
+            return;
+        }
+        let kept_bytes: Vec<u8> = self.view().range(len / 3 * 3..len);
+        self.i32_data.truncate(len / 3);
+        self.i32_data
+            .extend(s_d_u8_i32::serialize_u8_to_i32(kept_bytes));
+    }
+
+    // Splits the buffer in two at byte at, self keeps [0, at) and the returned buffer holds [at, len)
+    pub fn split_off(&mut self, at: usize) -> EncodedBytes {
+        let len: usize = self.len();
+        assert!(
+            at <= len,
+            "split index {} out of bounds for {} bytes",
+            at,
+            len
+        );
+        let other: EncodedBytes = if at.is_multiple_of(3) {
+            // The split falls between words, nothing needs re-packing
+            EncodedBytes {
+                i32_data: self.i32_data.split_off(at / 3),
+            }
+        } else {
+            EncodedBytes::encode(self.view().range(at..))
+        };
+        self.truncate(at);
+        other
+    }
+
+    pub fn splice<R: RangeBounds<usize>>(&mut self, range: R, replacement: &[u8]) {
+        splice(&mut self.i32_data, range, replacement);
+    }
+
+    pub fn remove<R: RangeBounds<usize>>(&mut self, range: R) {
+        remove(&mut self.i32_data, range);
+    }
+
+    pub fn iter(&self) -> Bytes<'_> {
+        Bytes {
+            i32_data: &self.i32_data,
+            index: 0,
+            len: self.len(),
+        }
+    }
+}
+
+impl TryFrom<Vec<i32>> for EncodedBytes {
+    type Error = InvalidEncoding;
+
+    fn try_from(i32_data: Vec<i32>) -> Result<EncodedBytes, InvalidEncoding> {
+        match validate(&i32_data).issues.first() {
+            Some(issue) => Err(InvalidEncoding {
+                index: issue.index,
+                word: issue.word,
+                reason: issue.reason,
+            }),
+            None => Ok(EncodedBytes { i32_data }),
+        }
+    }
+}
+
+impl From<EncodedBytes> for Vec<i32> {
+    fn from(encoded: EncodedBytes) -> Vec<i32> {
+        encoded.i32_data
+    }
+}
+
+impl AsRef<[i32]> for EncodedBytes {
+    fn as_ref(&self) -> &[i32] {
+        &self.i32_data
+    }
+}
+
+impl fmt::Debug for EncodedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EncodedBytes")
+            .field("len", &self.len())
+            .field("words", &self.i32_data.len())
+            .finish()
+    }
+}
+
+impl FromIterator<u8> for EncodedBytes {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> EncodedBytes {
+        EncodedBytes::encode(iter.into_iter().collect())
+    }
+}
+
+impl Extend<u8> for EncodedBytes {
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+        let u8_data: Vec<u8> = iter.into_iter().collect();
+        self.extend_from_slice(&u8_data);
+    }
+}
+
+impl IntoIterator for EncodedBytes {
+    type Item = u8;
+    type IntoIter = std::vec::IntoIter<u8>;
+
+    fn into_iter(self) -> std::vec::IntoIter<u8> {
+        s_d_u8_i32::deserialize_i32_to_u8(self.i32_data).into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a EncodedBytes {
+    type Item = u8;
+    type IntoIter = Bytes<'a>;
+
+    fn into_iter(self) -> Bytes<'a> {
+        self.iter()
+    }
+}
+
+// Iterates over the bytes of an EncodedBytes, reading each word only when it is reached
+#[derive(Clone, Debug)]
+pub struct Bytes<'a> {
+    i32_data: &'a [i32],
+    index: usize,
+    len: usize,
+}
+
+impl Iterator for Bytes<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.index < self.len {
+            self.index += 1;
+            Some(byte_at(self.i32_data, self.index - 1))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len - self.index, Some(self.len - self.index))
+    }
+}
+
+impl ExactSizeIterator for Bytes<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    fn bytes(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 37 % 256) as u8).collect()
+    }
+
+    #[test]
+    fn test_extend_from_slice_repacks_the_tail() {
+        let mut buffer = EncodedBytes::encode(vec![9, 10, 11, 12, 13]);
+        assert_eq!(buffer.as_words(), &[1009010011, 2000012013]);
+        buffer.extend_from_slice(&[14, 15, 16]);
+        assert_eq!(buffer.as_words(), &[1009010011, 1012013014, 2000015016]);
+        assert_eq!(buffer.len(), 8);
+    }
+
+    #[test]
+    fn test_extend_and_append_match_serialize() {
+        for a in 0..8 {
+            for b in 0..8 {
+                let mut expected: Vec<u8> = bytes(a);
+                expected.extend(bytes(b));
+                let mut extended = EncodedBytes::encode(bytes(a));
+                extended.extend_from_slice(&bytes(b));
+                assert_eq!(
+                    extended.as_words(),
+                    s_d_u8_i32::serialize_u8_to_i32(expected.clone()).as_slice()
+                );
+                let mut appended = EncodedBytes::encode(bytes(a));
+                let mut other = EncodedBytes::encode(bytes(b));
+                appended.append(&mut other);
+                assert!(other.is_empty());
+                assert_eq!(
+                    appended.as_words(),
+                    s_d_u8_i32::serialize_u8_to_i32(expected).as_slice()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncate_and_split_off_match_serialize() {
+        for length in 0..12 {
+            let data: Vec<u8> = bytes(length);
+            for at in 0..=length {
+                let mut truncated = EncodedBytes::encode(data.clone());
+                truncated.truncate(at);
+                assert_eq!(
+                    truncated.as_words(),
+                    s_d_u8_i32::serialize_u8_to_i32(data[..at].to_vec()).as_slice()
+                );
+                let mut front = EncodedBytes::encode(data.clone());
+                let back: EncodedBytes = front.split_off(at);
+                assert_eq!(front.decode(), data[..at].to_vec());
+                assert_eq!(
+                    back.as_words(),
+                    s_d_u8_i32::serialize_u8_to_i32(data[at..].to_vec()).as_slice()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_splice() {
+        let mut v: Vec<i32> = vec![1009010011, 1012013014, 2000015016];
+        splice(&mut v, 4..5, &[100, 101]);
+        assert_eq!(v, vec![1009010011, 1012100101, 1014015016]);
+        remove(&mut v, ..3);
+        assert_eq!(v, vec![1012100101, 1014015016]);
+        remove(&mut v, 5..);
+        assert_eq!(v, vec![1012100101, 2000014015]);
+    }
+
+    #[test]
+    fn test_splice_and_remove_match_reference() {
+        // Random edits, each checked against decoding, editing the Vec<u8> and encoding again
+        let mut rng = XorShift::new(0x2545_f491_4f6c_dd1d);
+        let mut next = move |limit: usize| -> usize { rng.below(limit + 1) };
+        for _ in 0..500 {
+            let data: Vec<u8> = bytes(next(20));
+            let start: usize = next(data.len());
+            let end: usize = start + next(data.len() - start);
+            let replacement: Vec<u8> = bytes(next(7)).iter().map(|b| b ^ 0x5a).collect();
+
+            let mut expected: Vec<u8> = data.clone();
+            expected.splice(start..end, replacement.iter().cloned());
+            let mut v: Vec<i32> = s_d_u8_i32::serialize_u8_to_i32(data.clone());
+            splice(&mut v, start..end, &replacement);
+            assert_eq!(v, s_d_u8_i32::serialize_u8_to_i32(expected));
+
+            let mut expected: Vec<u8> = data.clone();
+            expected.drain(start..end);
+            let mut buffer = EncodedBytes::encode(data);
+            buffer.remove(start..end);
+            assert_eq!(
+                buffer.as_words(),
+                s_d_u8_i32::serialize_u8_to_i32(expected).as_slice()
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_splice_out_of_bounds() {
+        let mut v: Vec<i32> = vec![2000015016];
+        splice(&mut v, 1..3, &[]);
+    }
+
+    #[test]
+    fn test_try_from() {
+        let encoded = EncodedBytes::try_from(vec![1009010011, 1012013014, 2000015016]).unwrap();
+        assert_eq!(encoded, EncodedBytes::encode((9..=16).collect()));
+        assert_eq!(
+            EncodedBytes::try_from(Vec::new()).unwrap(),
+            EncodedBytes::new()
+        );
+        assert_eq!(
+            EncodedBytes::try_from(vec![105]).unwrap().decode(),
+            vec![105]
+        );
+        // A tail word in the middle
+        assert_eq!(
+            EncodedBytes::try_from(vec![2000015016, 1009010011]),
+            Err(InvalidEncoding {
+                index: 0,
+                word: 2000015016,
+                reason: IssueReason::TailNotLast
+            })
+        );
+        // A group above 255
+        assert_eq!(
+            EncodedBytes::try_from(vec![1009256011]),
+            Err(InvalidEncoding {
+                index: 0,
+                word: 1009256011,
+                reason: IssueReason::GroupOutOfRange { position: 6 }
+            })
+        );
+        // A mode 2 word with a non-zero high group, a mode 0 word above 255 and a negative word
+        assert!(EncodedBytes::try_from(vec![2001015016]).is_err());
+        assert!(EncodedBytes::try_from(vec![256]).is_err());
+        assert!(EncodedBytes::try_from(vec![-1]).is_err());
+    }
+
+    #[test]
+    fn test_traits() {
+        let encoded: EncodedBytes = (9..=16).collect();
+        assert_eq!(encoded.as_ref(), &[1009010011, 1012013014, 2000015016]);
+        assert_eq!(
+            format!("{:?}", encoded),
+            "EncodedBytes { len: 8, words: 3 }"
+        );
+        assert_eq!((&encoded).into_iter().len(), 8);
+        assert_eq!(
+            (&encoded).into_iter().collect::<Vec<u8>>(),
+            (9..=16).collect::<Vec<u8>>()
+        );
+        let mut extended: EncodedBytes = encoded.clone();
+        extended.extend(vec![17, 18]);
+        assert_eq!(
+            extended.into_iter().collect::<Vec<u8>>(),
+            (9..=18).collect::<Vec<u8>>()
+        );
+        let words: Vec<i32> = encoded.into();
+        assert_eq!(words.len(), 3);
+    }
+
+    #[test]
+    fn test_truncate_longer_than_len() {
+        let mut buffer = EncodedBytes::encode(vec![1, 2]);
+        buffer.truncate(10);
+        assert_eq!(buffer.decode(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,252 @@
+//! Forward error correction for stored vectors using one XOR parity word per block of words.
+//!
+//! `add_parity` writes the block size first and then, after every block of up to that many words, the XOR of the
+//! block's words. The words must not be negative, so neither is their XOR. On the way back a single word per block
+//! which is known to be lost or is negative is rebuilt from the rest of its block. For `serialize_u8_to_i32` output
+//! `Detection::EncodedWords` also rebuilds words which that could not have written.
+use crate::validate::{check_word, is_tail_word, Severity};
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FecError {
+    MalformedHeader,
+    // The block's words do not match its parity, but no single word could be blamed
+    ParityMismatch { block: usize },
+    // More than one word of the block is lost or corrupt
+    Uncorrectable { block: usize },
+}
+
+impl fmt::Display for FecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FecError::MalformedHeader => write!(f, "malformed parity header"),
+            FecError::ParityMismatch { block } => {
+                write!(f, "block {} does not match its parity word", block)
+            }
+            FecError::Uncorrectable { block } => {
+                write!(f, "block {} has more than one bad word", block)
+            }
+        }
+    }
+}
+
+impl Error for FecError {}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Detection {
+    // Only erased and negative words are bad, which holds for any vector add_parity accepts
+    #[default]
+    Generic,
+    // Words deserialize_i32_to_u8 can not decode, and tail words before the last word, are bad too
+    EncodedWords,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recovered {
+    pub i32_data: Vec<i32>,
+    // Indices (into the recovered data) of the words which had to be rebuilt
+    pub repaired: Vec<usize>,
+}
+
+fn parity(words: &[i32]) -> i32 {
+    words.iter().fold(0, |parity, word| parity ^ word)
+}
+
+pub fn add_parity(i32_data: &[i32], block_size: usize) -> Vec<i32> {
+    assert!(block_size > 0, "block size must be at least 1 word");
+    assert!(
+        i32_data.iter().all(|word| *word >= 0),
+        "parity can only be added to non-negative words"
+    );
+    let mut protected: Vec<i32> = vec![block_size.try_into().unwrap()];
+    for block in i32_data.chunks(block_size) {
+        protected.extend_from_slice(block);
+        protected.push(parity(block));
+    }
+    protected
+}
+
+pub fn remove_parity(protected: &[i32]) -> Result<Recovered, FecError> {
+    remove_parity_with(protected, &[], Detection::default())
+}
+
+// As remove_parity, with the indices (into the protected vector) of words which are already known to be lost
+pub fn remove_parity_with_erasures(
+    protected: &[i32],
+    erased: &[usize],
+) -> Result<Recovered, FecError> {
+    remove_parity_with(protected, erased, Detection::default())
+}
+
+pub fn remove_parity_with(
+    protected: &[i32],
+    erased: &[usize],
+    detection: Detection,
+) -> Result<Recovered, FecError> {
+    let (block_size, body) = protected.split_first().ok_or(FecError::MalformedHeader)?;
+    let block_size: usize = match (*block_size).try_into() {
+        Ok(block_size) if block_size > 0 => block_size,
+        _ => return Err(FecError::MalformedHeader),
+    };
+    // A final block needs at least one data word besides its parity word
+    if body.len() % (block_size + 1) == 1 {
+        return Err(FecError::MalformedHeader);
+    }
+    let erased: HashSet<usize> = erased.iter().cloned().collect();
+    let data_len: usize = body.len() - body.len().div_ceil(block_size + 1);
+    let mut recovered = Recovered {
+        i32_data: Vec::new(),
+        repaired: Vec::new(),
+    };
+    for (block, words) in body.chunks(block_size + 1).enumerate() {
+        let (parity_word, data) = words.split_last().unwrap();
+        let first_index: usize = block * block_size;
+        let is_bad = |i: usize, word: i32| -> bool {
+            if erased.contains(&(1 + block * (block_size + 1) + i)) || word < 0 {
+                return true;
+            }
+            // The parity word is not an encoded word itself, so it can only be judged by its sign
+            detection == Detection::EncodedWords
+                && i < data.len()
+                && (check_word(word)
+                    .iter()
+                    .any(|reason| reason.severity() == Severity::Invalid)
+                    // Only the very last data word may be a tail word
+                    || (is_tail_word(word) && first_index + i + 1 != data_len))
+        };
+        let bad: Vec<usize> = words
+            .iter()
+            .enumerate()
+            .filter(|(i, word)| is_bad(*i, **word))
+            .map(|(i, _)| i)
+            .collect();
+        let mut data: Vec<i32> = data.to_vec();
+        match bad.as_slice() {
+            [] => {
+                if parity(&data) != *parity_word {
+                    return Err(FecError::ParityMismatch { block });
+                }
+            }
+            // Only the parity word itself is bad, the data is fine as it is
+            [i] if *i == data.len() => {}
+            [i] => {
+                data[*i] = 0;
+                data[*i] = parity(&data) ^ parity_word;
+                recovered.repaired.push(first_index + i);
+            }
+            _ => return Err(FecError::Uncorrectable { block }),
+        }
+        recovered.i32_data.extend(data);
+    }
+    Ok(recovered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s_d_u8_i32;
+
+    fn encoded(length: usize) -> Vec<i32> {
+        s_d_u8_i32::serialize_u8_to_i32((0..length).map(|i| (i * 7) as u8).collect())
+    }
+
+    #[test]
+    fn test_add_parity() {
+        let v: Vec<i32> = add_parity(&[1009010011, 1012013014, 2000015016], 2);
+        assert_eq!(
+            v,
+            vec![
+                2,
+                1009010011,
+                1012013014,
+                1009010011 ^ 1012013014,
+                2000015016,
+                2000015016
+            ]
+        );
+        assert!(v.iter().all(|word| *word >= 0));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for length in 0..30 {
+            let v: Vec<i32> = encoded(length);
+            let recovered: Recovered = remove_parity(&add_parity(&v, 4)).unwrap();
+            assert_eq!(recovered.i32_data, v);
+            assert!(recovered.repaired.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_repair_corrupt_word() {
+        let v: Vec<i32> = encoded(30);
+        let mut protected: Vec<i32> = add_parity(&v, 4);
+        // Word 5 of the data sits at index 7 (after the header, four data words and a parity word)
+        protected[7] = 1999000000;
+        assert_eq!(
+            remove_parity(&protected),
+            Err(FecError::ParityMismatch { block: 1 })
+        );
+        let recovered: Recovered =
+            remove_parity_with(&protected, &[], Detection::EncodedWords).unwrap();
+        assert_eq!(recovered.i32_data, v);
+        assert_eq!(recovered.repaired, vec![5]);
+    }
+
+    #[test]
+    fn test_round_trip_non_codec_vectors() {
+        let mut log: Vec<i32> = Vec::new();
+        crate::tlv::append_record(&mut log, 7, &[1, 2, 3, 4]);
+        crate::tlv::append_record(&mut log, 8, &[5]);
+        let container: Vec<i32> =
+            crate::container::encode_container(&[("a", &[1, 2, 3, 4]), ("b", &[5])]);
+        let counters: Vec<i32> = vec![0, 7, 999, 123456, i32::MAX];
+        for v in [log, container, counters] {
+            let mut protected: Vec<i32> = add_parity(&v, 4);
+            assert_eq!(remove_parity(&protected).unwrap().i32_data, v);
+            protected[2] = -1;
+            let recovered: Recovered = remove_parity(&protected).unwrap();
+            assert_eq!(recovered.i32_data, v);
+            assert_eq!(recovered.repaired, vec![1]);
+        }
+    }
+
+    #[test]
+    fn test_repair_erased_word() {
+        let v: Vec<i32> = encoded(30);
+        let mut protected: Vec<i32> = add_parity(&v, 4);
+        // A plausible looking but wrong word can only be repaired when its position is known
+        protected[2] = 1000000000;
+        assert_eq!(
+            remove_parity(&protected),
+            Err(FecError::ParityMismatch { block: 0 })
+        );
+        let recovered: Recovered = remove_parity_with_erasures(&protected, &[2]).unwrap();
+        assert_eq!(recovered.i32_data, v);
+        assert_eq!(recovered.repaired, vec![1]);
+    }
+
+    #[test]
+    fn test_corrupt_parity_word() {
+        let v: Vec<i32> = encoded(12);
+        let mut protected: Vec<i32> = add_parity(&v, 4);
+        protected[5] = -1;
+        assert_eq!(remove_parity(&protected).unwrap().i32_data, v);
+    }
+
+    #[test]
+    fn test_uncorrectable() {
+        let mut protected: Vec<i32> = add_parity(&encoded(30), 4);
+        protected[1] = -1;
+        protected[2] = -1;
+        assert_eq!(
+            remove_parity(&protected),
+            Err(FecError::Uncorrectable { block: 0 })
+        );
+        assert_eq!(remove_parity(&[]), Err(FecError::MalformedHeader));
+        assert_eq!(remove_parity(&[0]), Err(FecError::MalformedHeader));
+    }
+}
@@ -0,0 +1,237 @@
+//! Reversible pre-filters for image rows and time series, applied before packing.
+//!
+//! Each filter replaces a byte with its difference (wrapping) from a prediction made from bytes before it, so
+//! correlated data turns into small, repetitive bytes which store and compress far better. `Delta` predicts from the
+//! byte `bytes_per_pixel` back anywhere in the stream; `Sub`, `Up`, `Average` and `Paeth` are the PNG filters,
+//! predicting from the pixel to the left, the one above and the one above and to the left within rows of
+//! `row_width` bytes.
+use crate::s_d_u8_i32;
+use crate::validate::first_invalid_word;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    None,
+    Delta,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Stride {
+    bytes_per_pixel: usize,
+    // Bytes per row, the last row may be shorter
+    row_width: usize,
+}
+
+impl Stride {
+    pub fn new(bytes_per_pixel: usize, row_width: usize) -> Stride {
+        assert!(bytes_per_pixel > 0, "bytes per pixel must be at least 1");
+        assert!(row_width > 0, "row width must be at least 1 byte");
+        Stride {
+            bytes_per_pixel,
+            row_width,
+        }
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bytes_per_pixel
+    }
+
+    pub fn row_width(&self) -> usize {
+        self.row_width
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    // A word deserialize_i32_to_u8 can not decode
+    InvalidWord { index: usize },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterError::InvalidWord { index } => write!(f, "word {} can not be decoded", index),
+        }
+    }
+}
+
+impl Error for FilterError {}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p: i16 = i16::from(a) + i16::from(b) - i16::from(c);
+    let pa: i16 = (p - i16::from(a)).abs();
+    let pb: i16 = (p - i16::from(b)).abs();
+    let pc: i16 = (p - i16::from(c)).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// The prediction for the byte at index, from the already unfiltered bytes before it
+fn predict(u8_data: &[u8], index: usize, filter: Filter, stride: Stride) -> u8 {
+    let column: usize = index % stride.row_width;
+    let left = |i: usize| -> u8 {
+        if column >= stride.bytes_per_pixel {
+            u8_data[i - stride.bytes_per_pixel]
+        } else {
+            0
+        }
+    };
+    let up = |i: usize| -> u8 {
+        if i >= stride.row_width {
+            u8_data[i - stride.row_width]
+        } else {
+            0
+        }
+    };
+    match filter {
+        Filter::None => 0,
+        Filter::Delta => {
+            if index >= stride.bytes_per_pixel {
+                u8_data[index - stride.bytes_per_pixel]
+            } else {
+                0
+            }
+        }
+        Filter::Sub => left(index),
+        Filter::Up => up(index),
+        Filter::Average => ((u16::from(left(index)) + u16::from(up(index))) / 2) as u8,
+        Filter::Paeth => {
+            let up_left: u8 = if index >= stride.row_width {
+                left(index - stride.row_width)
+            } else {
+                0
+            };
+            paeth(left(index), up(index), up_left)
+        }
+    }
+}
+
+pub fn apply_filter(u8_data: &[u8], filter: Filter, stride: Stride) -> Vec<u8> {
+    (0..u8_data.len())
+        .map(|index| u8_data[index].wrapping_sub(predict(u8_data, index, filter, stride)))
+        .collect()
+}
+
+pub fn unapply_filter(filtered: &[u8], filter: Filter, stride: Stride) -> Vec<u8> {
+    let mut vec_of_u8s: Vec<u8> = Vec::with_capacity(filtered.len());
+    for (index, byte) in filtered.iter().enumerate() {
+        // Predictions only look backwards, so the bytes they need are already restored
+        let prediction: u8 = predict(&vec_of_u8s, index, filter, stride);
+        vec_of_u8s.push(byte.wrapping_add(prediction));
+    }
+    vec_of_u8s
+}
+
+pub fn filter_then_encode(u8_data: Vec<u8>, filter: Filter, stride: Stride) -> Vec<i32> {
+    s_d_u8_i32::serialize_u8_to_i32(apply_filter(&u8_data, filter, stride))
+}
+
+pub fn decode_then_unfilter(
+    _i32_data: Vec<i32>,
+    filter: Filter,
+    stride: Stride,
+) -> Result<Vec<u8>, FilterError> {
+    if let Some(index) = first_invalid_word(&_i32_data) {
+        return Err(FilterError::InvalidWord { index });
+    }
+    Ok(unapply_filter(
+        &s_d_u8_i32::deserialize_i32_to_u8(_i32_data),
+        filter,
+        stride,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    const FILTERS: [Filter; 6] = [
+        Filter::None,
+        Filter::Delta,
+        Filter::Sub,
+        Filter::Up,
+        Filter::Average,
+        Filter::Paeth,
+    ];
+
+    // An RGB gradient, 4 pixels per row
+    fn gradient() -> Vec<u8> {
+        (0..5)
+            .flat_map(|y: u8| (0..4).flat_map(move |x: u8| vec![10 * x + y, 20 + y, 200 - 3 * x]))
+            .collect()
+    }
+
+    #[test]
+    fn test_delta() {
+        let stride = Stride::new(1, 1);
+        assert_eq!(
+            apply_filter(&[10, 12, 15, 14, 0], Filter::Delta, stride),
+            vec![10, 2, 3, 255, 242]
+        );
+    }
+
+    #[test]
+    fn test_sub_and_up() {
+        let stride = Stride::new(3, 12);
+        let image: Vec<u8> = gradient();
+        let sub: Vec<u8> = apply_filter(&image, Filter::Sub, stride);
+        // The first pixel of a row is kept, the rest are differences from the pixel before
+        assert_eq!(
+            &sub[..12],
+            &[0, 20, 200, 10, 0, 253, 10, 0, 253, 10, 0, 253]
+        );
+        let up: Vec<u8> = apply_filter(&image, Filter::Up, stride);
+        assert_eq!(&up[..3], &[0, 20, 200]);
+        assert!(up[12..].iter().all(|byte| *byte <= 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "row width must be at least 1 byte")]
+    fn test_zero_row_width() {
+        Stride::new(1, 0);
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 20, 30), 10);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let noise: Vec<u8> = XorShift::new(99991).bytes(101);
+        for data in [gradient(), noise] {
+            for filter in FILTERS.iter() {
+                for stride in [Stride::new(1, 1), Stride::new(3, 12), Stride::new(2, 7)] {
+                    let v: Vec<i32> = filter_then_encode(data.clone(), *filter, stride);
+                    assert_eq!(decode_then_unfilter(v, *filter, stride).unwrap(), data);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_word() {
+        let stride = Stride::new(1, 1);
+        assert_eq!(
+            decode_then_unfilter(vec![1999000000], Filter::Delta, stride),
+            Err(FilterError::InvalidWord { index: 0 })
+        );
+        assert_eq!(
+            decode_then_unfilter(vec![1010002001, 256], Filter::Delta, stride),
+            Err(FilterError::InvalidWord { index: 1 })
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Canonical Huffman coding for text-heavy blobs, as a dependency-free alternative to plain decimal packing.
+//!
+//! The first word holds the number of bytes and the second the number of distinct bytes used. The code table follows
+//! as `(byte, code length)` pairs packed with `serialize_u8_to_i32`; since the codes are canonical the lengths are
+//! all that is needed to rebuild them. The rest is the bitstream (each code most significant bit first) packed with
+//! `serialize_bits_to_i32`. Code lengths are capped at `MAX_CODE_LENGTH` by flattening the byte frequencies until
+//! the tree is shallow enough.
+use crate::s_d_bits_i32;
+use crate::s_d_u8_i32::{self, exceeding_max_i32_threshold};
+use crate::validate::first_invalid_word;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+pub const MAX_CODE_LENGTH: u8 = 15;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HuffmanError {
+    // Fewer words than the header, code table and bitstream need
+    Truncated,
+    MalformedTable,
+    // The bitstream holds a bit pattern which is not a code, or ends in the middle of one
+    InvalidCode { bit_offset: usize },
+}
+
+impl fmt::Display for HuffmanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HuffmanError::Truncated => write!(f, "huffman data is truncated"),
+            HuffmanError::MalformedTable => write!(f, "malformed huffman code table"),
+            HuffmanError::InvalidCode { bit_offset } => {
+                write!(f, "no valid code at bit {}", bit_offset)
+            }
+        }
+    }
+}
+
+impl Error for HuffmanError {}
+
+// The code length of every byte value (0 for bytes which do not occur)
+pub fn code_lengths(u8_data: &[u8]) -> [u8; 256] {
+    let mut frequencies: [u64; 256] = [0; 256];
+    for byte in u8_data {
+        frequencies[*byte as usize] += 1;
+    }
+    loop {
+        let lengths: [u8; 256] = tree_depths(&frequencies);
+        if lengths.iter().all(|length| *length <= MAX_CODE_LENGTH) {
+            return lengths;
+        }
+        // Halving (while keeping every used byte) evens out the frequencies, which makes the tree shallower
+        for frequency in frequencies.iter_mut().filter(|frequency| **frequency > 0) {
+            *frequency = frequency.div_ceil(2);
+        }
+    }
+}
+
+fn tree_depths(frequencies: &[u64; 256]) -> [u8; 256] {
+    let mut lengths: [u8; 256] = [0; 256];
+    let mut parents: Vec<usize> = vec![usize::MAX; 256];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = frequencies
+        .iter()
+        .enumerate()
+        .filter(|(_, frequency)| **frequency > 0)
+        .map(|(byte, frequency)| Reverse((*frequency, byte)))
+        .collect();
+    if heap.len() == 1 {
+        // A lone byte still needs a one bit code
+        let Reverse((_, byte)) = heap.pop().unwrap();
+        lengths[byte] = 1;
+        return lengths;
+    }
+    while heap.len() > 1 {
+        let Reverse((first_weight, first)) = heap.pop().unwrap();
+        let Reverse((second_weight, second)) = heap.pop().unwrap();
+        let node: usize = parents.len();
+        parents.push(usize::MAX);
+        parents[first] = node;
+        parents[second] = node;
+        heap.push(Reverse((first_weight + second_weight, node)));
+    }
+    for (byte, length) in lengths.iter_mut().enumerate() {
+        let mut node: usize = byte;
+        while parents[node] != usize::MAX {
+            node = parents[node];
+            *length += 1;
+        }
+    }
+    lengths
+}
+
+// The used bytes in canonical order, by code length and then by value
+fn canonical_order(lengths: &[u8; 256]) -> Vec<u8> {
+    let mut symbols: Vec<u8> = (0..=255)
+        .filter(|byte| lengths[*byte as usize] > 0)
+        .collect();
+    symbols.sort_by_key(|byte| (lengths[*byte as usize], *byte));
+    symbols
+}
+
+// The canonical code of every byte value, as (code, length)
+fn canonical_codes(lengths: &[u8; 256]) -> Vec<(u32, u8)> {
+    let mut codes: Vec<(u32, u8)> = vec![(0, 0); 256];
+    let mut code: u32 = 0;
+    let mut previous_length: u8 = 0;
+    for byte in canonical_order(lengths) {
+        let length: u8 = lengths[byte as usize];
+        code <<= length - previous_length;
+        codes[byte as usize] = (code, length);
+        code += 1;
+        previous_length = length;
+    }
+    codes
+}
+
+pub fn encode_huffman(u8_data: Vec<u8>) -> Vec<i32> {
+    if exceeding_max_i32_threshold(u8_data.len() as u64) {
+        return Vec::new();
+    }
+    let lengths: [u8; 256] = code_lengths(&u8_data);
+    let symbols: Vec<u8> = canonical_order(&lengths);
+    let table: Vec<u8> = symbols
+        .iter()
+        .flat_map(|byte| vec![*byte, lengths[*byte as usize]])
+        .collect();
+    let codes: Vec<(u32, u8)> = canonical_codes(&lengths);
+    let mut bit_data: Vec<bool> = Vec::new();
+    for byte in &u8_data {
+        let (code, length) = codes[*byte as usize];
+        for bit in (0..length).rev() {
+            bit_data.push(code >> bit & 1 == 1);
+        }
+    }
+    let mut vec_of_i32s: Vec<i32> = vec![
+        u8_data.len().try_into().unwrap(),
+        symbols.len().try_into().unwrap(),
+    ];
+    vec_of_i32s.extend(s_d_u8_i32::serialize_u8_to_i32(table));
+    vec_of_i32s.extend(s_d_bits_i32::serialize_bits_to_i32(bit_data));
+    vec_of_i32s
+}
+
+pub fn decode_huffman(_i32_data: Vec<i32>) -> Result<Vec<u8>, HuffmanError> {
+    if _i32_data.len() < 2 {
+        return Err(HuffmanError::Truncated);
+    }
+    let byte_len: usize = _i32_data[0]
+        .try_into()
+        .map_err(|_| HuffmanError::MalformedTable)?;
+    let symbol_count: usize = match _i32_data[1].try_into() {
+        Ok(symbol_count) if symbol_count <= 256 => symbol_count,
+        _ => return Err(HuffmanError::MalformedTable),
+    };
+    let table_words: usize = (2 * symbol_count).div_ceil(3);
+    if _i32_data.len() < 2 + table_words {
+        return Err(HuffmanError::Truncated);
+    }
+    let table_data: &[i32] = &_i32_data[2..2 + table_words];
+    if first_invalid_word(table_data).is_some() {
+        return Err(HuffmanError::MalformedTable);
+    }
+    let table: Vec<u8> = s_d_u8_i32::deserialize_i32_to_u8(table_data.to_vec());
+    if table.len() != 2 * symbol_count {
+        return Err(HuffmanError::MalformedTable);
+    }
+    let mut lengths: [u8; 256] = [0; 256];
+    for pair in table.chunks(2) {
+        let (byte, length) = (pair[0] as usize, pair[1]);
+        if length == 0 || length > MAX_CODE_LENGTH || lengths[byte] != 0 {
+            return Err(HuffmanError::MalformedTable);
+        }
+        lengths[byte] = length;
+    }
+    // The codes must fit in the code space (Kraft's inequality), otherwise they can not be prefix free
+    let kraft: u32 = lengths
+        .iter()
+        .filter(|length| **length > 0)
+        .map(|length| 1 << (MAX_CODE_LENGTH - length))
+        .sum();
+    if kraft > 1 << MAX_CODE_LENGTH {
+        return Err(HuffmanError::MalformedTable);
+    }
+    let symbols: Vec<u8> = canonical_order(&lengths);
+    let mut counts: [u32; MAX_CODE_LENGTH as usize + 1] = [0; MAX_CODE_LENGTH as usize + 1];
+    for byte in &symbols {
+        counts[lengths[*byte as usize] as usize] += 1;
+    }
+    // The bitstream needs its bit count word and enough words to hold that many bits
+    let bit_data: Vec<bool> =
+        s_d_bits_i32::deserialize_i32_to_bits_checked(&_i32_data[2 + table_words..])
+            .map_err(|_| HuffmanError::Truncated)?;
+    let mut vec_of_u8s: Vec<u8> = Vec::with_capacity(byte_len.min(bit_data.len()));
+    let mut bits = bit_data.iter().enumerate();
+    while vec_of_u8s.len() < byte_len {
+        // Walk down the canonical code lengths, at each length the codes form one contiguous range
+        let mut code: u32 = 0;
+        let mut first: u32 = 0;
+        let mut index: u32 = 0;
+        let mut start: usize = bit_data.len();
+        let mut decoded: Option<u8> = None;
+        for count in counts.iter().skip(1) {
+            let (bit_offset, bit) = match bits.next() {
+                Some(bit) => bit,
+                None => break,
+            };
+            start = start.min(bit_offset);
+            code |= u32::from(*bit);
+            if code < first + count {
+                decoded = Some(symbols[(index + code - first) as usize]);
+                break;
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        match decoded {
+            Some(byte) => vec_of_u8s.push(byte),
+            None => return Err(HuffmanError::InvalidCode { bit_offset: start }),
+        }
+    }
+    Ok(vec_of_u8s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    #[test]
+    fn test_canonical_codes() {
+        let lengths: [u8; 256] = code_lengths(b"aaaabbc");
+        assert_eq!((lengths[b'a' as usize], lengths[b'b' as usize]), (1, 2));
+        assert_eq!(lengths[b'c' as usize], 2);
+        let codes: Vec<(u32, u8)> = canonical_codes(&lengths);
+        assert_eq!(codes[b'a' as usize], (0b0, 1));
+        assert_eq!(codes[b'b' as usize], (0b10, 2));
+        assert_eq!(codes[b'c' as usize], (0b11, 2));
+    }
+
+    #[test]
+    fn test_encode_huffman() {
+        let v: Vec<i32> = encode_huffman(b"aaaabbc".to_vec());
+        // 7 bytes, 3 symbols, the table a:1 b:2 c:2, then 10 bits 0000 10 10 11
+        assert_eq!(v, vec![7, 3, 1097001098, 1002099002, 10, 0b11_0101_0000]);
+        assert_eq!(decode_huffman(v).unwrap(), b"aaaabbc".to_vec());
+    }
+
+    #[test]
+    fn test_text_shrinks() {
+        let text: Vec<u8> =
+            b"the quick brown fox jumps over the lazy dog, again and again. ".repeat(20);
+        let v: Vec<i32> = encode_huffman(text.clone());
+        assert!(v.len() < s_d_u8_i32::serialize_u8_to_i32(text.clone()).len() / 2);
+        assert_eq!(decode_huffman(v).unwrap(), text);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = XorShift::new(7);
+        for length in 0..100 {
+            let data: Vec<u8> = rng.bytes_from(&[0, 31, 62, 93, 124, 155, 186], length);
+            assert_eq!(decode_huffman(encode_huffman(data.clone())).unwrap(), data);
+        }
+        assert_eq!(
+            decode_huffman(encode_huffman(vec![42; 10])).unwrap(),
+            vec![42; 10]
+        );
+    }
+
+    #[test]
+    fn test_code_lengths_are_limited() {
+        // Fibonacci frequencies give the deepest possible tree
+        let mut data: Vec<u8> = Vec::new();
+        let (mut a, mut b): (usize, usize) = (1, 1);
+        for byte in 0..25 {
+            data.extend(vec![byte; a]);
+            let next: usize = a + b;
+            a = b;
+            b = next;
+        }
+        let lengths: [u8; 256] = code_lengths(&data);
+        assert!(lengths.iter().all(|length| *length <= MAX_CODE_LENGTH));
+        assert_eq!(decode_huffman(encode_huffman(data.clone())).unwrap(), data);
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        assert_eq!(decode_huffman(vec![1]), Err(HuffmanError::Truncated));
+        assert_eq!(
+            decode_huffman(vec![1, 300]),
+            Err(HuffmanError::MalformedTable)
+        );
+        // Two codes of length 1 and one of length 2 do not fit
+        assert_eq!(
+            decode_huffman(vec![1, 3, 1097001098, 1001099002, 0]),
+            Err(HuffmanError::MalformedTable)
+        );
+        let mut v: Vec<i32> = encode_huffman(b"aaaabbc".to_vec());
+        v[0] = 8;
+        assert_eq!(
+            decode_huffman(v),
+            Err(HuffmanError::InvalidCode { bit_offset: 10 })
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Order-preserving encoding of byte strings for sortable storage index keys.
+//!
+//! Full words from `serialize_u8_to_i32` already sort like their bytes, but tail words do not, and neither does a
+//! string against its own extension. Here every word is a full `1aaabbbccc` word whose groups hold each byte plus
+//! one (`001` to `256`), leaving `000` free to pad the last word. That padding is the terminator: a key whose length
+//! is a multiple of three gets a whole padding word `1000000000`. Every key therefore ends in exactly one word with
+//! a `000` group, so no key is a prefix of another and comparing encoded slices equals comparing the byte strings.
+//! The same holds for composite keys made by concatenating encoded keys.
+use crate::s_d_u8_i32::access_value;
+use std::error::Error;
+use std::fmt;
+
+pub const KEY_TERMINATOR: i32 = 1000000000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyError {
+    // The words run out before a terminating word
+    NotTerminated,
+    // A word which encode_key never writes
+    InvalidWord { index: usize, word: i32 },
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyError::NotTerminated => write!(f, "key is not terminated"),
+            KeyError::InvalidWord { index, word } => {
+                write!(f, "word {} ({}) is not a key word", index, word)
+            }
+        }
+    }
+}
+
+impl Error for KeyError {}
+
+pub fn encode_key(u8_data: &[u8]) -> Vec<i32> {
+    let mut vec_of_i32s: Vec<i32> = Vec::new();
+    encode_key_into(&mut vec_of_i32s, u8_data);
+    vec_of_i32s
+}
+
+// Appends the key to i32_data, so that composite keys can be built part by part
+pub fn encode_key_into(i32_data: &mut Vec<i32>, u8_data: &[u8]) {
+    for chunk in u8_data.chunks(3) {
+        let mut single_i32: i32 = 1000000000;
+        for (i, byte) in chunk.iter().enumerate() {
+            single_i32 += (i32::from(*byte) + 1) * 1000_i32.pow(2 - i as u32);
+        }
+        i32_data.push(single_i32);
+    }
+    if u8_data.len().is_multiple_of(3) {
+        i32_data.push(KEY_TERMINATOR);
+    }
+}
+
+// Decodes the key at the start of i32_data, returning the bytes and the number of words it took up
+pub fn decode_key_prefix(i32_data: &[i32]) -> Result<(Vec<u8>, usize), KeyError> {
+    let mut vec_of_u8s: Vec<u8> = Vec::new();
+    for (index, word) in i32_data.iter().enumerate() {
+        let invalid = KeyError::InvalidWord { index, word: *word };
+        if *word < KEY_TERMINATOR || access_value(*word as u64, 10, 1) != 1 {
+            return Err(invalid);
+        }
+        let mut terminated: bool = false;
+        for position in &[9, 6, 3] {
+            let group: u64 = access_value(*word as u64, *position, 3);
+            if terminated || group == 0 {
+                // Once the padding starts every remaining group must be padding too
+                if group != 0 {
+                    return Err(invalid);
+                }
+                terminated = true;
+            } else if group > 256 {
+                return Err(invalid);
+            } else {
+                vec_of_u8s.push((group - 1) as u8);
+            }
+        }
+        if terminated {
+            return Ok((vec_of_u8s, index + 1));
+        }
+    }
+    Err(KeyError::NotTerminated)
+}
+
+pub fn decode_key(i32_data: &[i32]) -> Result<Vec<u8>, KeyError> {
+    let (vec_of_u8s, used) = decode_key_prefix(i32_data)?;
+    if used != i32_data.len() {
+        return Err(KeyError::InvalidWord {
+            index: used,
+            word: i32_data[used],
+        });
+    }
+    Ok(vec_of_u8s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s_d_u8_i32;
+    use crate::test_util::XorShift;
+
+    // Short strings over a few byte values, including the extremes, so that prefixes and ties are common
+    fn random_key(rng: &mut XorShift) -> Vec<u8> {
+        let length: usize = rng.below(8);
+        rng.bytes_from(&[0, 1, 127, 254, 255], length)
+    }
+
+    #[test]
+    fn test_encode_key() {
+        assert_eq!(encode_key(b""), vec![KEY_TERMINATOR]);
+        assert_eq!(encode_key(&[0, 255]), vec![1001256000]);
+        assert_eq!(encode_key(&[9, 10, 11]), vec![1010011012, KEY_TERMINATOR]);
+        assert_eq!(encode_key(&[9, 10, 11, 12]), vec![1010011012, 1013000000]);
+    }
+
+    #[test]
+    fn test_plain_encoding_is_not_ordered() {
+        // The reason this module exists: [1, 0, 0] sorts below [2] as bytes but not as plain words
+        let (a, b): (Vec<u8>, Vec<u8>) = (vec![1, 0, 0], vec![2]);
+        assert!(a < b);
+        assert!(
+            s_d_u8_i32::serialize_u8_to_i32(a.clone()) > s_d_u8_i32::serialize_u8_to_i32(b.clone())
+        );
+        assert!(encode_key(&a) < encode_key(&b));
+    }
+
+    #[test]
+    fn test_order_matches_bytes() {
+        let mut rng = XorShift::new(2463534242);
+        for _ in 0..5000 {
+            let (a, b): (Vec<u8>, Vec<u8>) = (random_key(&mut rng), random_key(&mut rng));
+            assert_eq!(
+                encode_key(&a).cmp(&encode_key(&b)),
+                a.cmp(&b),
+                "{:?} {:?}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn test_sorting_keys_sorts_bytes() {
+        let mut rng = XorShift::new(99);
+        let mut keys: Vec<Vec<u8>> = (0..500).map(|_| random_key(&mut rng)).collect();
+        let mut encoded: Vec<Vec<i32>> = keys.iter().map(|key| encode_key(key)).collect();
+        keys.sort();
+        encoded.sort();
+        let decoded: Vec<Vec<u8>> = encoded.iter().map(|key| decode_key(key).unwrap()).collect();
+        assert_eq!(decoded, keys);
+    }
+
+    #[test]
+    fn test_composite_keys() {
+        let mut rng = XorShift::new(7);
+        for _ in 0..2000 {
+            let (a, b, c, d) = (
+                random_key(&mut rng),
+                random_key(&mut rng),
+                random_key(&mut rng),
+                random_key(&mut rng),
+            );
+            let mut left: Vec<i32> = encode_key(&a);
+            encode_key_into(&mut left, &b);
+            let mut right: Vec<i32> = encode_key(&c);
+            encode_key_into(&mut right, &d);
+            assert_eq!(left.cmp(&right), (&a, &b).cmp(&(&c, &d)));
+            let (first, used) = decode_key_prefix(&left).unwrap();
+            assert_eq!(first, a);
+            assert_eq!(decode_key(&left[used..]).unwrap(), b);
+        }
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert_eq!(decode_key(&[]), Err(KeyError::NotTerminated));
+        assert_eq!(decode_key(&[1010011012]), Err(KeyError::NotTerminated));
+        assert!(decode_key(&[2000000000]).is_err());
+        assert_eq!(
+            decode_key(&[1001000002]),
+            Err(KeyError::InvalidWord {
+                index: 0,
+                word: 1001000002
+            })
+        );
+        assert_eq!(
+            decode_key(&[1257000000]),
+            Err(KeyError::InvalidWord {
+                index: 0,
+                word: 1257000000
+            })
+        );
+        assert_eq!(
+            decode_key(&[KEY_TERMINATOR, KEY_TERMINATOR]),
+            Err(KeyError::InvalidWord {
+                index: 1,
+                word: KEY_TERMINATOR
+            })
+        );
+    }
+}
@@ -125,12 +125,8 @@ pub mod s_d_u8_i32 {
     use std::convert::TryInto;
 
     pub fn exceeding_max_i32_threshold(_num: u64) -> bool {
-        let max: u64 = i32::max_value().try_into().unwrap();
-        if _num > max {
-            true
-        } else {
-            false
-        }
+        let max: u64 = i32::MAX.try_into().unwrap();
+        _num > max
     }
 
     pub fn count_vec_items_left(_vec: &Vec<u8>) -> u64 {
@@ -153,7 +149,7 @@ pub mod s_d_u8_i32 {
     ) -> u64 {
         // buffer up the single value to equal size i.e. turn 55 (two digits) into 055 (three digits) where the size is 3 etc.
         let mut string_single_value = _single_value.to_string();
-        while string_single_value.len() < (_size as u64).try_into().unwrap() {
+        while string_single_value.len() < _size.try_into().unwrap() {
             string_single_value = "0".to_owned() + &string_single_value;
         }
         let new_single_value: u64 = string_single_value.parse::<u64>().unwrap();
@@ -173,8 +169,8 @@ pub mod s_d_u8_i32 {
     pub fn serialize_u8_to_i32(u8_data: Vec<u8>) -> Vec<i32> {
         let mut vec_of_i32s: Vec<i32> = Vec::new();
         // Test to see if there are too many i32s to store (we need to store the number of i32s in the first i32 so this can not exceed 2147483647)
-        if exceeding_max_i32_threshold(count_vec_items_left(&u8_data).into()) == false {
-            let items_left: u64 = count_vec_items_left(&u8_data).try_into().unwrap();
+        if !exceeding_max_i32_threshold(count_vec_items_left(&u8_data)) {
+            let items_left: u64 = count_vec_items_left(&u8_data);
             // Begin processing all of the data into i32s
             let batches_left: u64 = items_left / 3;
             //println!("Batches to process: {:?}", batches_left);
@@ -199,13 +195,13 @@ pub mod s_d_u8_i32 {
                     //println!("Three: {:?}", three);
                     //single_value_for_i32_vec = flush_value_to_zero(single_value_for_i32_vec, 9, 3);
                     single_value_for_i32_vec =
-                        insert_value_at_position(single_value_for_i32_vec, one as u64, 9, 3);
+                        insert_value_at_position(single_value_for_i32_vec, one, 9, 3);
                     //single_value_for_i32_vec = flush_value_to_zero(single_value_for_i32_vec, 6, 3);
                     single_value_for_i32_vec =
-                        insert_value_at_position(single_value_for_i32_vec, two as u64, 6, 3);
+                        insert_value_at_position(single_value_for_i32_vec, two, 6, 3);
                     //single_value_for_i32_vec = flush_value_to_zero(single_value_for_i32_vec, 3, 3);
                     single_value_for_i32_vec =
-                        insert_value_at_position(single_value_for_i32_vec, three as u64, 3, 3);
+                        insert_value_at_position(single_value_for_i32_vec, three, 3, 3);
                     vec_of_i32s.push(single_value_for_i32_vec.try_into().unwrap());
                 }
             }
@@ -226,7 +222,7 @@ pub mod s_d_u8_i32 {
                 //println!("One: {:?}", one);
                 //single_value_for_i32_vec = flush_value_to_zero(single_value_for_i32_vec, 3, 3);
                 single_value_for_i32_vec =
-                    insert_value_at_position(single_value_for_i32_vec, one as u64, 3, 3);
+                    insert_value_at_position(single_value_for_i32_vec, one, 3, 3);
                 // Set the indicator to 3
                 single_value_for_i32_vec = flush_value_to_zero(single_value_for_i32_vec, 10, 1);
                 // A single u8 stored in a single i32 will have a prefix of 3 - this is a code used in encoding/decoding
@@ -244,10 +240,10 @@ pub mod s_d_u8_i32 {
                 //println!("Two: {:?}", two);
                 //single_value_for_i32_vec = flush_value_to_zero(single_value_for_i32_vec, 6, 3);
                 single_value_for_i32_vec =
-                    insert_value_at_position(single_value_for_i32_vec, one as u64, 6, 3);
+                    insert_value_at_position(single_value_for_i32_vec, one, 6, 3);
                 //single_value_for_i32_vec = flush_value_to_zero(single_value_for_i32_vec, 3, 3);
                 single_value_for_i32_vec =
-                    insert_value_at_position(single_value_for_i32_vec, two as u64, 3, 3);
+                    insert_value_at_position(single_value_for_i32_vec, two, 3, 3);
                 // Set the indicator to 2
                 single_value_for_i32_vec = flush_value_to_zero(single_value_for_i32_vec, 10, 1);
                 // When two u8s are stored in a single i32 it will have a prefix of 2 - this is a code used in encoding/decoding
@@ -308,7 +304,76 @@ pub mod s_d_u8_i32 {
     }
 }
 
+pub mod auto;
+pub mod checksum;
+pub mod container;
+pub mod dedup;
+pub mod encoded_bytes;
+pub mod fec;
+pub mod filter;
+pub mod huffman;
+pub mod key;
+pub mod lossy;
+pub mod lz;
+pub mod pipeline;
+pub mod rle;
+pub mod s_d_bits_i32;
+pub mod s_d_i8_i32;
+pub mod s_d_nibbles_i32;
+pub mod s_d_u16_i32;
+pub mod search;
+pub mod shard;
+pub mod storage;
+pub mod tlv;
+pub mod validate;
+pub mod view;
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    // A seeded xorshift64 generator, so tests get varied but repeatable data without pulling in a crate
+    pub(crate) struct XorShift(u64);
+
+    impl XorShift {
+        pub(crate) fn new(seed: u64) -> XorShift {
+            // Xorshift never leaves a zero state
+            XorShift(seed.max(1))
+        }
+
+        pub(crate) fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        // A value from 0 up to but not including limit
+        pub(crate) fn below(&mut self, limit: usize) -> usize {
+            (self.next_u64() % limit as u64) as usize
+        }
+
+        pub(crate) fn byte(&mut self) -> u8 {
+            (self.next_u64() >> 56) as u8
+        }
+
+        pub(crate) fn bytes(&mut self, length: usize) -> Vec<u8> {
+            (0..length).map(|_| self.byte()).collect()
+        }
+
+        // Bytes drawn from the alphabet only
+        pub(crate) fn bytes_from(&mut self, alphabet: &[u8], length: usize) -> Vec<u8> {
+            (0..length)
+                .map(|_| alphabet[self.below(alphabet.len())])
+                .collect()
+        }
+    }
+}
+
 #[cfg(test)]
+#[allow(
+    clippy::vec_init_then_push,
+    clippy::bool_assert_comparison,
+    clippy::zero_prefixed_literal
+)]
 mod tests {
     use super::s_d_u8_i32;
     #[test]
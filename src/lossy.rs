@@ -0,0 +1,109 @@
+//! A lenient decoder which salvages as much as possible from a corrupted `serialize_u8_to_i32` vector.
+//!
+//! Where `deserialize_i32_to_u8` would panic on a bad digit group, `decode_lossy` substitutes a fill byte (or skips
+//! the byte altogether) and carries on, reporting every problem it found along with the index of the word.
+use crate::s_d_u8_i32::access_value;
+use crate::validate::{validate, word_mode, ValidationIssue};
+use std::convert::TryInto;
+
+// The issues found while decoding are the same ones validate reports
+pub type DecodeIssue = ValidationIssue;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    // Write this byte in place of each byte which can not be recovered
+    Fill(u8),
+    // Leave unrecoverable bytes out of the output
+    Skip,
+}
+
+impl Default for Recovery {
+    fn default() -> Recovery {
+        Recovery::Fill(0)
+    }
+}
+
+pub fn decode_lossy(i32_data: &[i32]) -> (Vec<u8>, Vec<DecodeIssue>) {
+    decode_lossy_with(i32_data, Recovery::default())
+}
+
+pub fn decode_lossy_with(i32_data: &[i32], recovery: Recovery) -> (Vec<u8>, Vec<DecodeIssue>) {
+    let mut vec_of_u8s: Vec<u8> = Vec::new();
+    let recover = |vec_of_u8s: &mut Vec<u8>| {
+        if let Recovery::Fill(fill) = recovery {
+            vec_of_u8s.push(fill);
+        }
+    };
+    for single_i32_from_vec in i32_data {
+        if *single_i32_from_vec < 0 {
+            // The mode is unknown, so assume the word was a full one
+            for _ in 0..3 {
+                recover(&mut vec_of_u8s);
+            }
+            continue;
+        }
+        let positions: &[u64] = match word_mode(*single_i32_from_vec) {
+            1 => &[9, 6, 3],
+            2 => &[6, 3],
+            _ => &[3],
+        };
+        for position in positions {
+            match access_value(*single_i32_from_vec as u64, *position, 3).try_into() {
+                Ok(byte) => vec_of_u8s.push(byte),
+                Err(_) => recover(&mut vec_of_u8s),
+            }
+        }
+    }
+    (vec_of_u8s, validate(i32_data).issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s_d_u8_i32;
+    use crate::validate::IssueReason;
+
+    #[test]
+    fn test_clean_input_matches_deserialize() {
+        let data: Vec<u8> = (0..20).map(|i| (i * 13) as u8).collect();
+        let v: Vec<i32> = s_d_u8_i32::serialize_u8_to_i32(data.clone());
+        assert_eq!(decode_lossy(&v), (data, Vec::new()));
+    }
+
+    #[test]
+    fn test_fill_corrupt_groups() {
+        // The middle group of the second word and the whole third word are corrupt
+        let v: Vec<i32> = vec![1009010011, 1012999014, -1, 2000015016];
+        let (data, issues) = decode_lossy_with(&v, Recovery::Fill(0xff));
+        assert_eq!(
+            data,
+            vec![9, 10, 11, 12, 0xff, 14, 0xff, 0xff, 0xff, 15, 16]
+        );
+        let reasons: Vec<(usize, IssueReason)> = issues
+            .iter()
+            .map(|issue| (issue.index, issue.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (1, IssueReason::GroupOutOfRange { position: 6 }),
+                (2, IssueReason::Negative)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skip_corrupt_groups() {
+        let v: Vec<i32> = vec![1009010011, 1012999014, -1, 2000015016];
+        let (data, issues) = decode_lossy_with(&v, Recovery::Skip);
+        assert_eq!(data, vec![9, 10, 11, 12, 14, 15, 16]);
+        assert_eq!(issues.len(), 2);
+    }
+
+    #[test]
+    fn test_never_panics() {
+        let v: Vec<i32> = vec![i32::MIN, i32::MAX, 999, 2999999999u32 as i32, 0, 1999999999];
+        let (_, issues) = decode_lossy(&v);
+        assert!(!issues.is_empty());
+    }
+}
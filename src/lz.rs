@@ -0,0 +1,269 @@
+//! A small dependency-free LZSS compressor, for documents and JSON blobs which repeat themselves a lot.
+//!
+//! The compressed stream is a sequence of groups, each a flag byte followed by up to eight items. Bit `i` (least
+//! significant first) of the flag byte says whether item `i` is a literal byte (0) or a back reference (1). A back
+//! reference is two bytes holding a 12 bit distance (1 to 4096, stored minus one) and a 4 bit length (3 to 18,
+//! stored minus three), so matches are only searched for in the last 4096 bytes.
+//!
+//! `compress_then_encode` puts a header word in front of the packed bytes, `RAW` when compression did not make the
+//! data any smaller (so the bytes are stored as they are) and `COMPRESSED` otherwise.
+use crate::s_d_u8_i32::{self, exceeding_max_i32_threshold};
+use crate::validate::first_invalid_word;
+use std::error::Error;
+use std::fmt;
+
+pub const RAW: i32 = 0;
+pub const COMPRESSED: i32 = 1;
+
+pub const WINDOW_SIZE: usize = 4096;
+pub const MIN_MATCH: usize = 3;
+pub const MAX_MATCH: usize = 18;
+
+// Hash chains are only followed this far, which keeps compression linear on highly repetitive input
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 12;
+const NO_POSITION: usize = usize::MAX;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LzError {
+    MissingHeader,
+    UnknownHeader(i32),
+    // A payload word deserialize_i32_to_u8 can not decode, by its index in the encoded vector
+    InvalidWord { index: usize },
+    // The stream ends in the middle of a back reference
+    Truncated { offset: usize },
+    // A back reference points before the start of the output
+    InvalidDistance { offset: usize, distance: usize },
+}
+
+impl fmt::Display for LzError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LzError::MissingHeader => write!(f, "missing compression header word"),
+            LzError::UnknownHeader(word) => write!(f, "unknown compression header word {}", word),
+            LzError::InvalidWord { index } => write!(f, "word {} can not be decoded", index),
+            LzError::Truncated { offset } => {
+                write!(
+                    f,
+                    "compressed stream ends inside the item at byte {}",
+                    offset
+                )
+            }
+            LzError::InvalidDistance { offset, distance } => write!(
+                f,
+                "back reference at byte {} reaches {} bytes before the start of the output",
+                offset, distance
+            ),
+        }
+    }
+}
+
+impl Error for LzError {}
+
+fn hash(bytes: &[u8]) -> usize {
+    let key: u32 = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+    (key.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+// The longest earlier match for the bytes at position, as (distance, length)
+fn longest_match(data: &[u8], position: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    let max_length: usize = MAX_MATCH.min(data.len() - position);
+    let mut best: (usize, usize) = (0, 0);
+    if max_length < MIN_MATCH {
+        return best;
+    }
+    let mut candidate: usize = head[hash(&data[position..])];
+    for _ in 0..MAX_CHAIN {
+        if candidate == NO_POSITION || position - candidate > WINDOW_SIZE {
+            break;
+        }
+        let length: usize = data[candidate..]
+            .iter()
+            .zip(&data[position..position + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best.1 {
+            best = (position - candidate, length);
+            if length == max_length {
+                break;
+            }
+        }
+        candidate = prev[candidate];
+    }
+    best
+}
+
+fn insert(data: &[u8], position: usize, head: &mut [usize], prev: &mut [usize]) {
+    if position + MIN_MATCH <= data.len() {
+        let h: usize = hash(&data[position..]);
+        prev[position] = head[h];
+        head[h] = position;
+    }
+}
+
+pub fn compress(u8_data: &[u8]) -> Vec<u8> {
+    let mut compressed: Vec<u8> = Vec::new();
+    let mut head: Vec<usize> = vec![NO_POSITION; 1 << HASH_BITS];
+    let mut prev: Vec<usize> = vec![NO_POSITION; u8_data.len()];
+    let mut flag_index: usize = 0;
+    let mut item: usize = 0;
+    let mut position: usize = 0;
+    while position < u8_data.len() {
+        if item.is_multiple_of(8) {
+            flag_index = compressed.len();
+            compressed.push(0);
+        }
+        let (distance, length) = longest_match(u8_data, position, &head, &prev);
+        if length >= MIN_MATCH {
+            compressed[flag_index] |= 1 << (item % 8);
+            let stored: usize = (distance - 1) << 4 | (length - MIN_MATCH);
+            compressed.push((stored >> 8) as u8);
+            compressed.push(stored as u8);
+        } else {
+            compressed.push(u8_data[position]);
+        }
+        let step: usize = length.max(1);
+        for p in position..position + step {
+            insert(u8_data, p, &mut head, &mut prev);
+        }
+        position += step;
+        item += 1;
+    }
+    compressed
+}
+
+pub fn decompress(compressed: &[u8]) -> Result<Vec<u8>, LzError> {
+    let mut vec_of_u8s: Vec<u8> = Vec::new();
+    let mut offset: usize = 0;
+    while offset < compressed.len() {
+        let flags: u8 = compressed[offset];
+        offset += 1;
+        for bit in 0..8 {
+            if offset >= compressed.len() {
+                break;
+            }
+            if flags & (1 << bit) == 0 {
+                vec_of_u8s.push(compressed[offset]);
+                offset += 1;
+                continue;
+            }
+            if offset + 2 > compressed.len() {
+                return Err(LzError::Truncated { offset });
+            }
+            let stored: usize =
+                usize::from(compressed[offset]) << 8 | usize::from(compressed[offset + 1]);
+            let distance: usize = (stored >> 4) + 1;
+            let length: usize = (stored & 0xf) + MIN_MATCH;
+            if distance > vec_of_u8s.len() {
+                return Err(LzError::InvalidDistance { offset, distance });
+            }
+            // Byte by byte, since a match may overlap the bytes it is producing
+            let start: usize = vec_of_u8s.len() - distance;
+            for i in start..start + length {
+                vec_of_u8s.push(vec_of_u8s[i]);
+            }
+            offset += 2;
+        }
+    }
+    Ok(vec_of_u8s)
+}
+
+pub fn compress_then_encode(u8_data: Vec<u8>) -> Vec<i32> {
+    if exceeding_max_i32_threshold(u8_data.len() as u64) {
+        return Vec::new();
+    }
+    let compressed: Vec<u8> = compress(&u8_data);
+    let (header, payload): (i32, Vec<u8>) = if compressed.len() < u8_data.len() {
+        (COMPRESSED, compressed)
+    } else {
+        (RAW, u8_data)
+    };
+    let mut vec_of_i32s: Vec<i32> = vec![header];
+    vec_of_i32s.extend(s_d_u8_i32::serialize_u8_to_i32(payload));
+    vec_of_i32s
+}
+
+pub fn decode_then_decompress(_i32_data: Vec<i32>) -> Result<Vec<u8>, LzError> {
+    let (header, words) = _i32_data.split_first().ok_or(LzError::MissingHeader)?;
+    if let Some(index) = first_invalid_word(words) {
+        return Err(LzError::InvalidWord { index: index + 1 });
+    }
+    let payload: Vec<u8> = s_d_u8_i32::deserialize_i32_to_u8(words.to_vec());
+    match *header {
+        RAW => Ok(payload),
+        COMPRESSED => decompress(&payload),
+        other => Err(LzError::UnknownHeader(other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    fn sample(length: usize) -> Vec<u8> {
+        // A small alphabet, so there is something to find
+        XorShift::new(2463534242).bytes_from(b"{\"key\": [1, 2, 3]}", length)
+    }
+
+    #[test]
+    fn test_compress() {
+        let compressed: Vec<u8> = compress(b"abcabcabcabc");
+        // Three literals, then one reference 3 bytes back for the remaining 9 bytes
+        assert_eq!(compressed, vec![0b1000, b'a', b'b', b'c', 0x00, 0x26]);
+        assert_eq!(decompress(&compressed).unwrap(), b"abcabcabcabc".to_vec());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for length in (0..300).chain(vec![5000, 20000]) {
+            let data: Vec<u8> = sample(length);
+            assert_eq!(decompress(&compress(&data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_repetitive_data_shrinks() {
+        let document: Vec<u8> = b"{\"id\": 1, \"name\": \"sensor\"}\n".repeat(100);
+        let v: Vec<i32> = compress_then_encode(document.clone());
+        assert_eq!(v[0], COMPRESSED);
+        assert!(v.len() < s_d_u8_i32::serialize_u8_to_i32(document.clone()).len() / 4);
+        assert_eq!(decode_then_decompress(v).unwrap(), document);
+    }
+
+    #[test]
+    fn test_incompressible_data_stored_raw() {
+        let data: Vec<u8> = vec![9, 10, 11, 12];
+        let v: Vec<i32> = compress_then_encode(data.clone());
+        assert_eq!(v, vec![RAW, 1009010011, 12]);
+        assert_eq!(decode_then_decompress(v).unwrap(), data);
+    }
+
+    #[test]
+    fn test_corrupt_input() {
+        assert_eq!(decode_then_decompress(vec![]), Err(LzError::MissingHeader));
+        assert_eq!(
+            decode_then_decompress(vec![7]),
+            Err(LzError::UnknownHeader(7))
+        );
+        assert_eq!(
+            decode_then_decompress(vec![COMPRESSED, 1999999999]),
+            Err(LzError::InvalidWord { index: 1 })
+        );
+        assert_eq!(
+            decode_then_decompress(vec![COMPRESSED, 1009010011, 2000256000]),
+            Err(LzError::InvalidWord { index: 2 })
+        );
+        assert_eq!(
+            decompress(&[0b1, 0x00]),
+            Err(LzError::Truncated { offset: 1 })
+        );
+        assert_eq!(
+            decompress(&[0b10, b'a', 0x01, 0x00]),
+            Err(LzError::InvalidDistance {
+                offset: 2,
+                distance: 17
+            })
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Composable encode pipelines with a matching decoder, built around `serialize_u8_to_i32` as the packing stage.
+//!
+//! A pipeline is declared stage by stage, i.e. `Pipeline::new().delta(4).rle().pack_decimal().checksum().framed()`.
+//! Byte stages (`delta`, `rle`, `lz`) come first, then `pack_decimal` turns the bytes into words and word stages
+//! (`checksum`) follow. `decode` undoes the stages in reverse order.
+//!
+//! Each stage is described by its id, the number of parameters and the parameters themselves, and the pipeline by
+//! the number of stages followed by every stage's description. A `framed` pipeline writes its description in front
+//! of the data, so `decode_framed` can decode it without knowing how it was encoded.
+use crate::checksum::checksum_u8;
+use crate::filter::{self, Filter, Stride};
+use crate::lz::{self, LzError};
+use crate::rle::{self, RleError};
+use crate::s_d_u8_i32;
+use crate::validate::first_invalid_word;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    // Byte delta with the given stride
+    Delta(usize),
+    RunLength,
+    Lz,
+    PackDecimal,
+    // A trailing word holding checksum_u8 of the big endian bytes of the packed words
+    Checksum,
+}
+
+impl Stage {
+    pub fn id(&self) -> i32 {
+        match self {
+            Stage::Delta(_) => 1,
+            Stage::RunLength => 2,
+            Stage::Lz => 3,
+            Stage::PackDecimal => 4,
+            Stage::Checksum => 5,
+        }
+    }
+
+    fn params(&self) -> Vec<i32> {
+        match self {
+            Stage::Delta(stride) => vec![(*stride).try_into().unwrap()],
+            _ => Vec::new(),
+        }
+    }
+
+    fn is_byte_stage(&self) -> bool {
+        match self {
+            Stage::Delta(_) | Stage::RunLength | Stage::Lz => true,
+            Stage::PackDecimal | Stage::Checksum => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PipelineError {
+    MalformedDescription,
+    UnknownStage(i32),
+    // The data was written by a different pipeline than the one decoding it
+    DescriptionMismatch,
+    MissingChecksum,
+    // A packed word deserialize_i32_to_u8 can not decode, by its index after the description
+    InvalidWord { index: usize },
+    ChecksumMismatch { expected: i32, actual: i32 },
+    RunLength(RleError),
+    Lz(LzError),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::MalformedDescription => write!(f, "malformed pipeline description"),
+            PipelineError::UnknownStage(id) => write!(f, "unknown pipeline stage {}", id),
+            PipelineError::DescriptionMismatch => {
+                write!(f, "data was encoded by a different pipeline")
+            }
+            PipelineError::MissingChecksum => write!(f, "missing checksum word"),
+            PipelineError::InvalidWord { index } => write!(f, "word {} can not be decoded", index),
+            PipelineError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {}, computed {}",
+                expected, actual
+            ),
+            PipelineError::RunLength(error) => write!(f, "{}", error),
+            PipelineError::Lz(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for PipelineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PipelineError::RunLength(error) => Some(error),
+            PipelineError::Lz(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<RleError> for PipelineError {
+    fn from(error: RleError) -> PipelineError {
+        PipelineError::RunLength(error)
+    }
+}
+
+impl From<LzError> for PipelineError {
+    fn from(error: LzError) -> PipelineError {
+        PipelineError::Lz(error)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    framed: bool,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    pub fn is_framed(&self) -> bool {
+        self.framed
+    }
+
+    fn is_packed(&self) -> bool {
+        self.stages.contains(&Stage::PackDecimal)
+    }
+
+    fn push(mut self, stage: Stage) -> Pipeline {
+        assert!(!self.framed, "framed must be the last call on a pipeline");
+        if stage.is_byte_stage() {
+            assert!(
+                !self.is_packed(),
+                "byte stages must come before pack_decimal"
+            );
+        } else if stage == Stage::PackDecimal {
+            assert!(!self.is_packed(), "a pipeline can only be packed once");
+        } else {
+            assert!(self.is_packed(), "word stages must come after pack_decimal");
+            assert!(
+                !self.stages.contains(&stage),
+                "a pipeline can only have one checksum"
+            );
+        }
+        self.stages.push(stage);
+        self
+    }
+
+    pub fn delta(self, stride: usize) -> Pipeline {
+        assert!(stride > 0, "delta stride must be at least 1");
+        assert!(
+            stride <= i32::MAX as usize,
+            "delta stride must fit in a description word"
+        );
+        self.push(Stage::Delta(stride))
+    }
+
+    pub fn rle(self) -> Pipeline {
+        self.push(Stage::RunLength)
+    }
+
+    pub fn lz(self) -> Pipeline {
+        self.push(Stage::Lz)
+    }
+
+    pub fn pack_decimal(self) -> Pipeline {
+        self.push(Stage::PackDecimal)
+    }
+
+    pub fn checksum(self) -> Pipeline {
+        self.push(Stage::Checksum)
+    }
+
+    pub fn framed(mut self) -> Pipeline {
+        assert!(self.is_packed(), "only a packed pipeline can be framed");
+        self.framed = true;
+        self
+    }
+
+    pub fn description(&self) -> Vec<i32> {
+        let mut description: Vec<i32> = vec![self.stages.len().try_into().unwrap()];
+        for stage in &self.stages {
+            let params: Vec<i32> = stage.params();
+            description.push(stage.id());
+            description.push(params.len().try_into().unwrap());
+            description.extend(params);
+        }
+        description
+    }
+
+    // Reads a description from the start of i32_data, returning the (framed) pipeline and the number of words read
+    pub fn from_description(i32_data: &[i32]) -> Result<(Pipeline, usize), PipelineError> {
+        let mut words = i32_data.iter().cloned();
+        let mut next = || words.next().ok_or(PipelineError::MalformedDescription);
+        let stage_count: i32 = next()?;
+        if stage_count < 0 {
+            return Err(PipelineError::MalformedDescription);
+        }
+        let mut read: usize = 1;
+        let mut stages: Vec<Stage> = Vec::new();
+        for _ in 0..stage_count {
+            let id: i32 = next()?;
+            let param_count: i32 = next()?;
+            let expected: i32 = match id {
+                1 => 1,
+                2..=5 => 0,
+                _ => return Err(PipelineError::UnknownStage(id)),
+            };
+            if param_count != expected {
+                return Err(PipelineError::MalformedDescription);
+            }
+            read += 2 + expected as usize;
+            stages.push(match id {
+                1 => match next()?.try_into() {
+                    Ok(stride) if stride > 0 => Stage::Delta(stride),
+                    _ => return Err(PipelineError::MalformedDescription),
+                },
+                2 => Stage::RunLength,
+                3 => Stage::Lz,
+                4 => Stage::PackDecimal,
+                _ => Stage::Checksum,
+            });
+        }
+        // The stages must be in an order the builder accepts
+        let packed: usize = stages
+            .iter()
+            .position(|stage| *stage == Stage::PackDecimal)
+            .ok_or(PipelineError::MalformedDescription)?;
+        let in_order: bool = stages[..packed].iter().all(Stage::is_byte_stage)
+            && stages[packed + 1..].len() <= 1
+            && stages[packed + 1..]
+                .iter()
+                .all(|stage| *stage == Stage::Checksum);
+        if !in_order {
+            return Err(PipelineError::MalformedDescription);
+        }
+        Ok((
+            Pipeline {
+                stages,
+                framed: true,
+            },
+            read,
+        ))
+    }
+
+    pub fn encode(&self, u8_data: &[u8]) -> Vec<i32> {
+        assert!(self.is_packed(), "a pipeline needs a pack_decimal stage");
+        let mut u8_data: Vec<u8> = u8_data.to_vec();
+        let mut vec_of_i32s: Vec<i32> = Vec::new();
+        if self.framed {
+            vec_of_i32s.extend(self.description());
+        }
+        let mut packed: Vec<i32> = Vec::new();
+        for stage in &self.stages {
+            match stage {
+                Stage::Delta(stride) => {
+                    u8_data = filter::apply_filter(&u8_data, Filter::Delta, delta_stride(*stride))
+                }
+                Stage::RunLength => u8_data = rle::encode_runs(&u8_data),
+                Stage::Lz => u8_data = lz::compress(&u8_data),
+                Stage::PackDecimal => {
+                    packed = s_d_u8_i32::serialize_u8_to_i32(std::mem::take(&mut u8_data))
+                }
+                Stage::Checksum => {
+                    let checksum: i32 = checksum_words(&packed);
+                    packed.push(checksum);
+                }
+            }
+        }
+        vec_of_i32s.extend(packed);
+        vec_of_i32s
+    }
+
+    pub fn decode(&self, i32_data: &[i32]) -> Result<Vec<u8>, PipelineError> {
+        let mut i32_data: &[i32] = i32_data;
+        if self.framed {
+            let (pipeline, read) = Pipeline::from_description(i32_data)?;
+            if pipeline.stages != self.stages {
+                return Err(PipelineError::DescriptionMismatch);
+            }
+            i32_data = &i32_data[read..];
+        }
+        let mut packed: Vec<i32> = i32_data.to_vec();
+        let mut u8_data: Vec<u8> = Vec::new();
+        for stage in self.stages.iter().rev() {
+            match stage {
+                Stage::Checksum => {
+                    let expected: i32 = packed.pop().ok_or(PipelineError::MissingChecksum)?;
+                    let actual: i32 = checksum_words(&packed);
+                    if expected != actual {
+                        return Err(PipelineError::ChecksumMismatch { expected, actual });
+                    }
+                }
+                Stage::PackDecimal => {
+                    if let Some(index) = first_invalid_word(&packed) {
+                        return Err(PipelineError::InvalidWord { index });
+                    }
+                    u8_data = s_d_u8_i32::deserialize_i32_to_u8(std::mem::take(&mut packed))
+                }
+                Stage::Lz => u8_data = lz::decompress(&u8_data)?,
+                Stage::RunLength => u8_data = rle::decode_runs(&u8_data)?,
+                Stage::Delta(stride) => {
+                    u8_data = filter::unapply_filter(&u8_data, Filter::Delta, delta_stride(*stride))
+                }
+            }
+        }
+        Ok(u8_data)
+    }
+}
+
+// Checksums the words themselves rather than the bytes they decode to, so a corrupt word is caught before decoding
+fn checksum_words(i32_data: &[i32]) -> i32 {
+    let bytes: Vec<u8> = i32_data
+        .iter()
+        .flat_map(|word| word.to_be_bytes().to_vec())
+        .collect();
+    checksum_u8(&bytes)
+}
+
+fn delta_stride(stride: usize) -> Stride {
+    // Delta ignores rows, any width will do
+    Stride::new(stride, 1)
+}
+
+pub fn decode_framed(i32_data: &[i32]) -> Result<Vec<u8>, PipelineError> {
+    let (pipeline, _) = Pipeline::from_description(i32_data)?;
+    pipeline.decode(i32_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    // A slowly rising 4 byte counter, as from a sensor
+    fn readings() -> Vec<u8> {
+        (0u32..300)
+            .flat_map(|i| (1000 + i / 10).to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_description() {
+        let pipeline = Pipeline::new()
+            .delta(4)
+            .rle()
+            .pack_decimal()
+            .checksum()
+            .framed();
+        assert_eq!(pipeline.description(), vec![4, 1, 1, 4, 2, 0, 4, 0, 5, 0]);
+        assert_eq!(
+            Pipeline::from_description(&pipeline.description()),
+            Ok((pipeline, 10))
+        );
+    }
+
+    #[test]
+    fn test_plain_pipeline_matches_serialize() {
+        let data: Vec<u8> = vec![9, 10, 11, 12];
+        let pipeline = Pipeline::new().pack_decimal();
+        assert_eq!(pipeline.encode(&data), vec![1009010011, 12]);
+        assert_eq!(pipeline.decode(&[1009010011, 12]).unwrap(), data);
+    }
+
+    #[test]
+    fn test_framed_round_trip() {
+        let data: Vec<u8> = readings();
+        let pipeline = Pipeline::new()
+            .delta(4)
+            .rle()
+            .pack_decimal()
+            .checksum()
+            .framed();
+        let v: Vec<i32> = pipeline.encode(&data);
+        assert!(v.len() < s_d_u8_i32::serialize_u8_to_i32(data.clone()).len() / 4);
+        assert_eq!(pipeline.decode(&v).unwrap(), data);
+        assert_eq!(decode_framed(&v).unwrap(), data);
+        assert_eq!(
+            Pipeline::new().pack_decimal().framed().decode(&v),
+            Err(PipelineError::DescriptionMismatch)
+        );
+    }
+
+    #[test]
+    fn test_every_stage_round_trips() {
+        let data: Vec<u8> = readings();
+        let pipelines: Vec<Pipeline> = vec![
+            Pipeline::new().lz().pack_decimal(),
+            Pipeline::new().rle().lz().pack_decimal().framed(),
+            Pipeline::new().delta(1).pack_decimal().checksum(),
+        ];
+        for pipeline in pipelines {
+            assert_eq!(pipeline.decode(&pipeline.encode(&data)).unwrap(), data);
+            assert_eq!(
+                pipeline.decode(&pipeline.encode(&[])).unwrap(),
+                Vec::<u8>::new()
+            );
+        }
+    }
+
+    #[test]
+    fn test_checksum_catches_corruption() {
+        let pipeline = Pipeline::new().pack_decimal().checksum();
+        let mut v: Vec<i32> = pipeline.encode(&[9, 10, 11, 12]);
+        v[0] = 1009010012;
+        assert!(matches!(
+            pipeline.decode(&v),
+            Err(PipelineError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(pipeline.decode(&[]), Err(PipelineError::MissingChecksum));
+        // A word with a group above 255 is caught by the checksum rather than panicking the decoder
+        let mut v: Vec<i32> = pipeline.encode(&[9, 10, 11, 12]);
+        v[0] = 1999999999;
+        assert!(matches!(
+            pipeline.decode(&v),
+            Err(PipelineError::ChecksumMismatch { .. })
+        ));
+        let mut rng = XorShift::new(3);
+        for length in 0..200 {
+            let data: Vec<u8> = rng.bytes(length);
+            assert_eq!(pipeline.decode(&pipeline.encode(&data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_invalid_word_without_checksum() {
+        assert_eq!(
+            Pipeline::new()
+                .pack_decimal()
+                .decode(&[1009010011, 1999999999]),
+            Err(PipelineError::InvalidWord { index: 1 })
+        );
+    }
+
+    #[test]
+    fn test_malformed_description() {
+        assert_eq!(decode_framed(&[]), Err(PipelineError::MalformedDescription));
+        assert_eq!(
+            decode_framed(&[1, 9, 0]),
+            Err(PipelineError::UnknownStage(9))
+        );
+        // Packing missing, and a byte stage after packing
+        assert_eq!(
+            decode_framed(&[1, 2, 0]),
+            Err(PipelineError::MalformedDescription)
+        );
+        assert_eq!(
+            decode_framed(&[2, 4, 0, 2, 0]),
+            Err(PipelineError::MalformedDescription)
+        );
+        assert_eq!(
+            decode_framed(&[3, 4, 0, 5, 0, 5, 0]),
+            Err(PipelineError::MalformedDescription)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "byte stages must come before pack_decimal")]
+    fn test_byte_stage_after_packing() {
+        Pipeline::new().pack_decimal().rle();
+    }
+
+    #[test]
+    #[should_panic(expected = "a pipeline can only have one checksum")]
+    fn test_second_checksum() {
+        Pipeline::new().pack_decimal().checksum().checksum();
+    }
+
+    #[test]
+    #[should_panic(expected = "word stages must come after pack_decimal")]
+    fn test_checksum_before_packing() {
+        Pipeline::new().checksum();
+    }
+}
@@ -0,0 +1,243 @@
+//! Run-length encoding for data with long runs of identical bytes (masks, flat image regions etc.).
+//!
+//! Runs are written as negative words, which `serialize_u8_to_i32` never produces, reading as the run length
+//! followed by the 3 digit byte i.e. 40 bytes of 255 become `-40255`. Everything else is packed exactly as
+//! `serialize_u8_to_i32` does, so a literal stretch which is not a multiple of three bytes ends in a tail word
+//! right before the next run word.
+//!
+//! `encode_runs` and `decode_runs` do the same at the byte level, for use before some other packing: a control
+//! byte below 128 is followed by that many plus one literal bytes, a control byte of 128 or more is followed by a
+//! single byte which repeats `control - 128 + MIN_RUN` times.
+use crate::s_d_u8_i32::{self, exceeding_max_i32_threshold};
+use crate::validate::first_invalid_word;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+// Runs shorter than this are cheaper (or no dearer) to store as ordinary triplets
+pub const MIN_RUN: usize = 4;
+// The longest run which fits in a single negative word
+pub const MAX_RUN: usize = 2147483;
+
+// The most literal bytes and the longest run a single control byte of encode_runs covers
+const MAX_LITERALS: usize = 128;
+const MAX_BYTE_RUN: usize = 127 + MIN_RUN;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RleError {
+    // The control byte at this offset is not followed by as many bytes as it announces
+    Truncated { offset: usize },
+}
+
+impl fmt::Display for RleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RleError::Truncated { offset } => {
+                write!(f, "run-length data ends inside the item at byte {}", offset)
+            }
+        }
+    }
+}
+
+impl Error for RleError {}
+
+fn run_word(_byte: u8, _count: usize) -> i32 {
+    let count: i32 = _count.try_into().unwrap();
+    -(count * 1000 + i32::from(_byte))
+}
+
+pub fn serialize_u8_to_i32_rle(u8_data: Vec<u8>) -> Vec<i32> {
+    let mut vec_of_i32s: Vec<i32> = Vec::new();
+    if exceeding_max_i32_threshold(u8_data.len() as u64) {
+        return vec_of_i32s;
+    }
+    let mut literal_start: usize = 0;
+    let mut index: usize = 0;
+    while index < u8_data.len() {
+        let byte: u8 = u8_data[index];
+        let run_length: usize = u8_data[index..]
+            .iter()
+            .take_while(|other| **other == byte)
+            .count();
+        if run_length >= MIN_RUN {
+            vec_of_i32s.extend(s_d_u8_i32::serialize_u8_to_i32(
+                u8_data[literal_start..index].to_vec(),
+            ));
+            let mut remaining: usize = run_length;
+            while remaining > 0 {
+                let count: usize = remaining.min(MAX_RUN);
+                vec_of_i32s.push(run_word(byte, count));
+                remaining -= count;
+            }
+            literal_start = index + run_length;
+        }
+        index += run_length;
+    }
+    vec_of_i32s.extend(s_d_u8_i32::serialize_u8_to_i32(
+        u8_data[literal_start..].to_vec(),
+    ));
+    vec_of_i32s
+}
+
+pub fn deserialize_i32_to_u8_rle(_i32_data: Vec<i32>) -> Vec<u8> {
+    let mut vec_of_u8s: Vec<u8> = Vec::new();
+    let mut literal_words: Vec<i32> = Vec::new();
+    for single_i32_from_vec in _i32_data {
+        if single_i32_from_vec < 0 {
+            vec_of_u8s.extend(s_d_u8_i32::deserialize_i32_to_u8(std::mem::take(
+                &mut literal_words,
+            )));
+            let run: i64 = -(single_i32_from_vec as i64);
+            let byte: u8 = (run % 1000).try_into().unwrap();
+            let count: usize = (run / 1000).try_into().unwrap();
+            vec_of_u8s.extend(std::iter::repeat_n(byte, count));
+        } else {
+            literal_words.push(single_i32_from_vec);
+        }
+    }
+    vec_of_u8s.extend(s_d_u8_i32::deserialize_i32_to_u8(literal_words));
+    vec_of_u8s
+}
+
+// The index of the first word deserialize_i32_to_u8_rle can not decode, i.e. a run word whose byte is above 255
+// or a literal word with an out of range group
+pub fn first_invalid_rle_word(_i32_data: &[i32]) -> Option<usize> {
+    _i32_data.iter().position(|word| {
+        if *word < 0 {
+            -(*word as i64) % 1000 > 255
+        } else {
+            first_invalid_word(&[*word]).is_some()
+        }
+    })
+}
+
+fn push_literals(encoded: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        encoded.push((chunk.len() - 1) as u8);
+        encoded.extend_from_slice(chunk);
+    }
+}
+
+pub fn encode_runs(u8_data: &[u8]) -> Vec<u8> {
+    let mut encoded: Vec<u8> = Vec::new();
+    let mut literal_start: usize = 0;
+    let mut index: usize = 0;
+    while index < u8_data.len() {
+        let byte: u8 = u8_data[index];
+        let run_length: usize = u8_data[index..]
+            .iter()
+            .take(MAX_BYTE_RUN)
+            .take_while(|other| **other == byte)
+            .count();
+        if run_length >= MIN_RUN {
+            push_literals(&mut encoded, &u8_data[literal_start..index]);
+            encoded.push((run_length - MIN_RUN + 128) as u8);
+            encoded.push(byte);
+            literal_start = index + run_length;
+        }
+        index += run_length;
+    }
+    push_literals(&mut encoded, &u8_data[literal_start..]);
+    encoded
+}
+
+pub fn decode_runs(encoded: &[u8]) -> Result<Vec<u8>, RleError> {
+    let mut vec_of_u8s: Vec<u8> = Vec::new();
+    let mut offset: usize = 0;
+    while offset < encoded.len() {
+        let control: usize = encoded[offset].into();
+        if control < 128 {
+            let literals: &[u8] = encoded
+                .get(offset + 1..offset + 2 + control)
+                .ok_or(RleError::Truncated { offset })?;
+            vec_of_u8s.extend_from_slice(literals);
+            offset += 2 + control;
+        } else {
+            let byte: u8 = *encoded
+                .get(offset + 1)
+                .ok_or(RleError::Truncated { offset })?;
+            vec_of_u8s.extend(std::iter::repeat_n(byte, control - 128 + MIN_RUN));
+            offset += 2;
+        }
+    }
+    Ok(vec_of_u8s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::XorShift;
+
+    #[test]
+    fn test_serialize_u8_to_i32_rle() {
+        let mut data: Vec<u8> = vec![9, 10, 11, 12];
+        data.extend(vec![255; 40]);
+        data.extend(vec![0, 0, 0, 1]);
+        let v: Vec<i32> = serialize_u8_to_i32_rle(data);
+        assert_eq!(v, vec![1009010011, 12, -40255, 1000000000, 1]);
+    }
+
+    #[test]
+    fn test_short_runs_stay_literal() {
+        let v: Vec<i32> = serialize_u8_to_i32_rle(vec![7, 7, 7, 8, 8, 8]);
+        assert_eq!(v, vec![1007007007, 1008008008]);
+    }
+
+    #[test]
+    fn test_mask_is_small() {
+        let mut mask: Vec<u8> = vec![0; 10_000];
+        mask.extend(vec![1; 5_000]);
+        let v: Vec<i32> = serialize_u8_to_i32_rle(mask.clone());
+        assert_eq!(v, vec![-10000000, -5000001]);
+        assert_eq!(deserialize_i32_to_u8_rle(v), mask);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = XorShift::new(12345);
+        for length in 0..200 {
+            // Few distinct values, so runs of all lengths turn up
+            let data: Vec<u8> = rng.bytes_from(&[0, 100, 200], length);
+            let v: Vec<i32> = serialize_u8_to_i32_rle(data.clone());
+            assert_eq!(deserialize_i32_to_u8_rle(v), data);
+        }
+    }
+
+    #[test]
+    fn test_first_invalid_rle_word() {
+        assert_eq!(first_invalid_rle_word(&[1009010011, -40255, 12]), None);
+        assert_eq!(first_invalid_rle_word(&[1009010011, -40256]), Some(1));
+        assert_eq!(first_invalid_rle_word(&[-4000, 1999999999]), Some(1));
+    }
+
+    #[test]
+    fn test_longest_run() {
+        assert_eq!(run_word(255, MAX_RUN), -2147483255);
+    }
+
+    #[test]
+    fn test_encode_runs() {
+        let mut data: Vec<u8> = vec![1, 2, 3];
+        data.extend(vec![0; 200]);
+        let encoded: Vec<u8> = encode_runs(&data);
+        assert_eq!(encoded, vec![2, 1, 2, 3, 255, 0, 193, 0]);
+        assert_eq!(decode_runs(&encoded).unwrap(), data);
+        assert_eq!(
+            decode_runs(&[2, 1, 2]),
+            Err(RleError::Truncated { offset: 0 })
+        );
+        assert_eq!(
+            decode_runs(&[0, 1, 200]),
+            Err(RleError::Truncated { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn test_runs_round_trip() {
+        let mut rng = XorShift::new(4242);
+        for length in (0..200).chain(vec![1000]) {
+            let data: Vec<u8> = rng.bytes_from(&[0, 1, 2], length);
+            assert_eq!(decode_runs(&encode_runs(&data)).unwrap(), data);
+        }
+    }
+}
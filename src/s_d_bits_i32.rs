@@ -0,0 +1,215 @@
+//! Packing boolean vectors (segmentation masks etc.) into i32, 31 bits per word.
+//!
+//! The first i32 holds the number of bits, every following i32 holds the next 31 bits with the first bit in the
+//! least significant position. The sign bit is never used so every word stays non-negative.
+//! Because unused bits are always zero, bitwise operations can be applied to the encoded words directly.
+use crate::s_d_u8_i32::exceeding_max_i32_threshold;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+pub const BITS_PER_I32: usize = 31;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BitsError {
+    // The vector is empty, so there is no bit count word
+    MissingBitCount,
+    NegativeBitCount(i32),
+    // Fewer words follow the bit count than it needs
+    Truncated { bit_count: usize, words: usize },
+}
+
+impl fmt::Display for BitsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BitsError::MissingBitCount => write!(f, "missing bit count word"),
+            BitsError::NegativeBitCount(bit_count) => {
+                write!(f, "bit count {} is negative", bit_count)
+            }
+            BitsError::Truncated { bit_count, words } => {
+                write!(f, "{} bits do not fit in {} words", bit_count, words)
+            }
+        }
+    }
+}
+
+impl Error for BitsError {}
+
+pub fn serialize_bits_to_i32(bit_data: Vec<bool>) -> Vec<i32> {
+    let mut vec_of_i32s: Vec<i32> = Vec::new();
+    if !exceeding_max_i32_threshold(bit_data.len() as u64) {
+        vec_of_i32s.push(bit_data.len().try_into().unwrap());
+        for chunk in bit_data.chunks(BITS_PER_I32) {
+            let mut single_value_for_i32_vec: i32 = 0;
+            for (position, bit) in chunk.iter().enumerate() {
+                if *bit {
+                    single_value_for_i32_vec |= 1 << position;
+                }
+            }
+            vec_of_i32s.push(single_value_for_i32_vec);
+        }
+    }
+    vec_of_i32s
+}
+
+// Panics on a negative bit count. A bit count larger than the words can hold is clamped to the bits present, use
+// deserialize_i32_to_bits_checked for vectors which may be truncated or corrupt
+pub fn deserialize_i32_to_bits(_i32_data: Vec<i32>) -> Vec<bool> {
+    let mut vec_of_bits: Vec<bool> = Vec::new();
+    if let Some((bit_count, words)) = _i32_data.split_first() {
+        let bit_count: usize = (*bit_count).try_into().unwrap();
+        for single_i32_from_vec in words {
+            for position in 0..BITS_PER_I32 {
+                if vec_of_bits.len() == bit_count {
+                    break;
+                }
+                vec_of_bits.push(single_i32_from_vec & (1 << position) != 0);
+            }
+        }
+    }
+    vec_of_bits
+}
+
+// The same as deserialize_i32_to_bits but returns an error instead of panicking on a negative bit count or
+// clamping a bit count which the words can not hold
+pub fn deserialize_i32_to_bits_checked(_i32_data: &[i32]) -> Result<Vec<bool>, BitsError> {
+    let (bit_count, words) = _i32_data.split_first().ok_or(BitsError::MissingBitCount)?;
+    let bit_count: usize = (*bit_count)
+        .try_into()
+        .map_err(|_| BitsError::NegativeBitCount(*bit_count))?;
+    if words.len() < bit_count.div_ceil(BITS_PER_I32) {
+        return Err(BitsError::Truncated {
+            bit_count,
+            words: words.len(),
+        });
+    }
+    Ok(deserialize_i32_to_bits(_i32_data.to_vec()))
+}
+
+// Combines two encoded bit vectors word by word, the result is as long as the longer of the two (missing bits count as false)
+fn combine_bits(_a: &[i32], _b: &[i32], operation: fn(i32, i32) -> i32) -> Vec<i32> {
+    let bit_count: i32 = (*_a.first().unwrap_or(&0)).max(*_b.first().unwrap_or(&0));
+    let words_a: &[i32] = _a.get(1..).unwrap_or(&[]);
+    let words_b: &[i32] = _b.get(1..).unwrap_or(&[]);
+    let mut vec_of_i32s: Vec<i32> = vec![bit_count];
+    for i in 0..words_a.len().max(words_b.len()) {
+        vec_of_i32s.push(operation(
+            *words_a.get(i).unwrap_or(&0),
+            *words_b.get(i).unwrap_or(&0),
+        ));
+    }
+    vec_of_i32s
+}
+
+pub fn and_bits(_a: &[i32], _b: &[i32]) -> Vec<i32> {
+    combine_bits(_a, _b, |a, b| a & b)
+}
+
+pub fn or_bits(_a: &[i32], _b: &[i32]) -> Vec<i32> {
+    combine_bits(_a, _b, |a, b| a | b)
+}
+
+pub fn xor_bits(_a: &[i32], _b: &[i32]) -> Vec<i32> {
+    combine_bits(_a, _b, |a, b| a ^ b)
+}
+
+pub fn popcount_bits(_i32_data: &[i32]) -> u64 {
+    _i32_data
+        .iter()
+        .skip(1)
+        .map(|word| u64::from(word.count_ones()))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits_from_str(_bits: &str) -> Vec<bool> {
+        _bits.chars().map(|c| c == '1').collect()
+    }
+
+    #[test]
+    fn test_serialize_bits_to_i32() {
+        let v: Vec<i32> = serialize_bits_to_i32(bits_from_str("1011"));
+        assert_eq!(v, vec![4, 0b1101]);
+    }
+
+    #[test]
+    fn test_serialize_bits_to_i32_spans_words() {
+        // 31 set bits fill the first word exactly, the 32nd lands in the second word
+        let v: Vec<i32> = serialize_bits_to_i32(vec![true; 32]);
+        assert_eq!(v, vec![32, i32::MAX, 1]);
+        assert!(v.iter().all(|word| *word >= 0));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for length in 0..100 {
+            let bits: Vec<bool> = (0..length).map(|i| i % 3 == 0 || i % 7 == 0).collect();
+            assert_eq!(
+                deserialize_i32_to_bits(serialize_bits_to_i32(bits.clone())),
+                bits
+            );
+        }
+    }
+
+    #[test]
+    fn test_deserialize_checked() {
+        let v: Vec<i32> = serialize_bits_to_i32(vec![true; 32]);
+        assert_eq!(deserialize_i32_to_bits_checked(&v), Ok(vec![true; 32]));
+        assert_eq!(
+            deserialize_i32_to_bits_checked(&[]),
+            Err(BitsError::MissingBitCount)
+        );
+        assert_eq!(
+            deserialize_i32_to_bits_checked(&[-1, 0]),
+            Err(BitsError::NegativeBitCount(-1))
+        );
+        assert_eq!(
+            deserialize_i32_to_bits_checked(&v[..2]),
+            Err(BitsError::Truncated {
+                bit_count: 32,
+                words: 1
+            })
+        );
+        // The unchecked decoder clamps to the bits present
+        assert_eq!(deserialize_i32_to_bits(vec![100, 0]), vec![false; 31]);
+    }
+
+    #[test]
+    fn test_bitwise_operations() {
+        let a: Vec<i32> =
+            serialize_bits_to_i32(bits_from_str("1100110011001100110011001100110011"));
+        let b: Vec<i32> =
+            serialize_bits_to_i32(bits_from_str("1010101010101010101010101010101010"));
+        assert_eq!(
+            deserialize_i32_to_bits(and_bits(&a, &b)),
+            bits_from_str("1000100010001000100010001000100010")
+        );
+        assert_eq!(
+            deserialize_i32_to_bits(or_bits(&a, &b)),
+            bits_from_str("1110111011101110111011101110111011")
+        );
+        assert_eq!(
+            deserialize_i32_to_bits(xor_bits(&a, &b)),
+            bits_from_str("0110011001100110011001100110011001")
+        );
+        assert_eq!(popcount_bits(&a), 18);
+        assert_eq!(popcount_bits(&and_bits(&a, &b)), 9);
+    }
+
+    #[test]
+    fn test_bitwise_operations_different_lengths() {
+        let a: Vec<i32> = serialize_bits_to_i32(bits_from_str("11"));
+        let b: Vec<i32> = serialize_bits_to_i32(bits_from_str("0101"));
+        assert_eq!(
+            deserialize_i32_to_bits(or_bits(&a, &b)),
+            bits_from_str("1101")
+        );
+        assert_eq!(
+            deserialize_i32_to_bits(and_bits(&a, &b)),
+            bits_from_str("0100")
+        );
+    }
+}
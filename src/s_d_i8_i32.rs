@@ -0,0 +1,107 @@
+//! Packing signed i8 data into i32 so that the stored decimals still show the real signed values.
+//!
+//! Each value occupies a 4 digit group made up of a sign flag (`1` for negative, `0` otherwise) followed by the
+//! 3 digit magnitude i.e. `-5` is `1005` and `7` is `0007`. A full word has a mode digit of 1 and holds two groups
+//! (`[-5, 7]` becomes `1010050007`), whilst an odd value at the end is stored alone with a mode of 0 (`[-128]` becomes `0000001128`).
+use crate::s_d_u8_i32::{access_value, exceeding_max_i32_threshold, insert_value_at_position};
+use std::convert::TryInto;
+
+fn signed_group(_value: i8) -> u64 {
+    let magnitude: u64 = (_value as i16).unsigned_abs().into();
+    if _value < 0 {
+        1000 + magnitude
+    } else {
+        magnitude
+    }
+}
+
+fn value_from_signed_group(_group: u64) -> i8 {
+    let magnitude: i16 = access_value(_group, 3, 3).try_into().unwrap();
+    if access_value(_group, 4, 1) == 1 {
+        (-magnitude).try_into().unwrap()
+    } else {
+        magnitude.try_into().unwrap()
+    }
+}
+
+pub fn serialize_i8_to_i32(i8_data: Vec<i8>) -> Vec<i32> {
+    let mut vec_of_i32s: Vec<i32> = Vec::new();
+    if !exceeding_max_i32_threshold(i8_data.len() as u64) {
+        for pair in i8_data.chunks(2) {
+            if pair.len() == 2 {
+                // Create a placeholder i32 with the mode set to 1
+                let mut single_value_for_i32_vec: u64 = 1000000000;
+                single_value_for_i32_vec =
+                    insert_value_at_position(single_value_for_i32_vec, signed_group(pair[0]), 8, 4);
+                single_value_for_i32_vec =
+                    insert_value_at_position(single_value_for_i32_vec, signed_group(pair[1]), 4, 4);
+                vec_of_i32s.push(single_value_for_i32_vec.try_into().unwrap());
+            } else {
+                // A single i8 stored in a single i32 will have a mode of 0
+                let single_value_for_i32_vec: u64 =
+                    insert_value_at_position(0, signed_group(pair[0]), 4, 4);
+                vec_of_i32s.push(single_value_for_i32_vec.try_into().unwrap());
+            }
+        }
+    }
+    vec_of_i32s
+}
+
+pub fn deserialize_i32_to_i8(_i32_data: Vec<i32>) -> Vec<i8> {
+    let mut vec_of_i8s: Vec<i8> = Vec::new();
+    for single_i32_from_vec in _i32_data {
+        let mode: u64 = access_value(single_i32_from_vec as u64, 10, 1);
+        if mode == 1 {
+            vec_of_i8s.push(value_from_signed_group(access_value(
+                single_i32_from_vec as u64,
+                8,
+                4,
+            )));
+        }
+        vec_of_i8s.push(value_from_signed_group(access_value(
+            single_i32_from_vec as u64,
+            4,
+            4,
+        )));
+    }
+    vec_of_i8s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_i8_to_i32() {
+        let v: Vec<i32> = serialize_i8_to_i32(vec![-5, 7, 127, -128, 0]);
+        assert_eq!(v, vec![1010050007, 1001271128, 0]);
+    }
+
+    #[test]
+    fn test_serialize_i8_to_i32_tail() {
+        let v: Vec<i32> = serialize_i8_to_i32(vec![-128]);
+        assert_eq!(v, vec![1128]);
+    }
+
+    #[test]
+    fn test_deserialize_i32_to_i8() {
+        let v: Vec<i8> = deserialize_i32_to_i8(vec![1010050007, 1128]);
+        assert_eq!(v, vec![-5, 7, -128]);
+    }
+
+    #[test]
+    fn test_round_trip_every_i8() {
+        for a in -128..=127_i8 {
+            // Every value alone, as a tail
+            assert_eq!(deserialize_i32_to_i8(serialize_i8_to_i32(vec![a])), vec![a]);
+            // And every value in both groups of a full word
+            for b in -128..=127_i8 {
+                let data: Vec<i8> = vec![a, b];
+                assert_eq!(
+                    deserialize_i32_to_i8(serialize_i8_to_i32(data.clone())),
+                    data
+                );
+            }
+        }
+    }
+}
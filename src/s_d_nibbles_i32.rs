@@ -0,0 +1,125 @@
+//! Packing 4-bit values (16 colour palette indices etc.) into i32, four 2-digit groups per word.
+//!
+//! Like serialize_u8_to_i32, a full word has a mode of 1 followed by the values i.e. `[1, 2, 3, 15]` becomes `1001020315`.
+//! The remaining one to three values at the end are stored in a tail word with a mode of 0, the digit after the mode
+//! holds how many values the tail contains and the values are right aligned i.e. `[15, 7]` becomes `0200001507`.
+use crate::s_d_u8_i32::{access_value, exceeding_max_i32_threshold, insert_value_at_position};
+use std::convert::TryInto;
+
+pub const NIBBLES_PER_I32: usize = 4;
+
+// Panics on a value above 15
+pub fn serialize_nibbles_to_i32(nibble_data: Vec<u8>) -> Vec<i32> {
+    let mut vec_of_i32s: Vec<i32> = Vec::new();
+    if !exceeding_max_i32_threshold(nibble_data.len() as u64) {
+        for batch in nibble_data.chunks(NIBBLES_PER_I32) {
+            // Create a placeholder i32, full words have a mode of 1 and tail words a mode of 0 plus a count
+            let mut single_value_for_i32_vec: u64 = if batch.len() == NIBBLES_PER_I32 {
+                1000000000
+            } else {
+                insert_value_at_position(0, batch.len() as u64, 9, 1)
+            };
+            // Values are right aligned so that the last one always sits in the lowest group
+            let mut position: u64 = 2 * batch.len() as u64;
+            for nibble in batch {
+                assert!(*nibble <= 15, "{} does not fit in a nibble", nibble);
+                single_value_for_i32_vec = insert_value_at_position(
+                    single_value_for_i32_vec,
+                    (*nibble).into(),
+                    position,
+                    2,
+                );
+                position -= 2;
+            }
+            vec_of_i32s.push(single_value_for_i32_vec.try_into().unwrap());
+        }
+    }
+    vec_of_i32s
+}
+
+// Panics on a word serialize_nibbles_to_i32 never writes: a negative word, a mode other than 0 or 1, a tail
+// count outside 1 to 3 or a group above 15
+pub fn deserialize_i32_to_nibbles(_i32_data: Vec<i32>) -> Vec<u8> {
+    let mut vec_of_nibbles: Vec<u8> = Vec::new();
+    for single_i32_from_vec in _i32_data {
+        assert!(
+            single_i32_from_vec >= 0,
+            "{} is not a nibble word",
+            single_i32_from_vec
+        );
+        let mode: u64 = access_value(single_i32_from_vec as u64, 10, 1);
+        let count: u64 = match mode {
+            1 => NIBBLES_PER_I32 as u64,
+            0 => access_value(single_i32_from_vec as u64, 9, 1),
+            _ => panic!("{} is not a nibble word", single_i32_from_vec),
+        };
+        assert!(
+            mode == 1 || (1..NIBBLES_PER_I32 as u64).contains(&count),
+            "{} does not hold 1 to {} tail nibbles",
+            single_i32_from_vec,
+            NIBBLES_PER_I32 - 1
+        );
+        for i in (1..=count).rev() {
+            let nibble: u64 = access_value(single_i32_from_vec as u64, 2 * i, 2);
+            assert!(nibble <= 15, "{} does not fit in a nibble", nibble);
+            vec_of_nibbles.push(nibble.try_into().unwrap());
+        }
+    }
+    vec_of_nibbles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_nibbles_to_i32() {
+        let v: Vec<i32> = serialize_nibbles_to_i32(vec![1, 2, 3, 15, 0, 9, 10, 11]);
+        assert_eq!(v, vec![1001020315, 1000091011]);
+    }
+
+    #[test]
+    fn test_serialize_nibbles_to_i32_tails() {
+        assert_eq!(serialize_nibbles_to_i32(vec![5]), vec![100000005]);
+        assert_eq!(serialize_nibbles_to_i32(vec![15, 7]), vec![200001507]);
+        assert_eq!(
+            serialize_nibbles_to_i32(vec![1, 2, 3, 4, 0, 0, 0]),
+            vec![1001020304, 300000000]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "16 does not fit in a nibble")]
+    fn test_serialize_nibbles_to_i32_out_of_range() {
+        serialize_nibbles_to_i32(vec![16]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for length in 0..20 {
+            let nibbles: Vec<u8> = (0..length).map(|i| (i * 7 % 16) as u8).collect();
+            assert_eq!(
+                deserialize_i32_to_nibbles(serialize_nibbles_to_i32(nibbles.clone())),
+                nibbles
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "99 does not fit in a nibble")]
+    fn test_deserialize_rejects_non_nibble() {
+        deserialize_i32_to_nibbles(vec![1000000099]);
+    }
+
+    #[test]
+    #[should_panic(expected = "2000000000 is not a nibble word")]
+    fn test_deserialize_rejects_other_modes() {
+        deserialize_i32_to_nibbles(vec![2000000000]);
+    }
+
+    #[test]
+    #[should_panic(expected = "900000000 does not hold 1 to 3 tail nibbles")]
+    fn test_deserialize_rejects_long_tail() {
+        deserialize_i32_to_nibbles(vec![900000000]);
+    }
+}
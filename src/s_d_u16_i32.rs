@@ -0,0 +1,101 @@
+//! Packing u16 samples (depth maps, PCM audio etc.) into i32, two 5-digit values per word where the range allows.
+//!
+//! A pair word simply reads as the two values side by side i.e. `[12, 65535]` becomes `0001265535`.
+//! Because i32 tops out at 2147483647 the first value of a pair can be at most 21474, so any value above that
+//! (as well as an odd value at the very end) is written on its own as a negative word with a `1` prefix i.e. `65535` becomes `-165535`.
+use crate::s_d_u8_i32::{access_value, exceeding_max_i32_threshold, insert_value_at_position};
+use std::convert::TryInto;
+
+// The largest value which can sit in the upper five digits of a pair word without exceeding i32::MAX
+pub const MAX_LEADING_PAIR_VALUE: u16 = 21474;
+
+pub fn serialize_u16_to_i32(u16_data: Vec<u16>) -> Vec<i32> {
+    let mut vec_of_i32s: Vec<i32> = Vec::new();
+    // Mirror serialize_u8_to_i32 and refuse to process more items than an i32 can count
+    if !exceeding_max_i32_threshold(u16_data.len() as u64) {
+        let mut index: usize = 0;
+        while index < u16_data.len() {
+            let one: u64 = u16_data[index].into();
+            if index + 1 < u16_data.len() && u16_data[index] <= MAX_LEADING_PAIR_VALUE {
+                // Two values side by side, both five digits wide
+                let two: u64 = u16_data[index + 1].into();
+                let mut single_value_for_i32_vec: u64 = 0;
+                single_value_for_i32_vec =
+                    insert_value_at_position(single_value_for_i32_vec, one, 10, 5);
+                single_value_for_i32_vec =
+                    insert_value_at_position(single_value_for_i32_vec, two, 5, 5);
+                vec_of_i32s.push(single_value_for_i32_vec.try_into().unwrap());
+                index += 2;
+            } else {
+                // A single value gets a prefix of 1 and is stored as a negative number
+                let single_value_for_i32_vec: u64 = insert_value_at_position(100000, one, 5, 5);
+                let single_value_for_i32_vec: i32 = single_value_for_i32_vec.try_into().unwrap();
+                vec_of_i32s.push(-single_value_for_i32_vec);
+                index += 1;
+            }
+        }
+    }
+    vec_of_i32s
+}
+
+pub fn deserialize_i32_to_u16(_i32_data: Vec<i32>) -> Vec<u16> {
+    let mut vec_of_u16s: Vec<u16> = Vec::new();
+    for single_i32_from_vec in _i32_data {
+        if single_i32_from_vec < 0 {
+            let single_value: u64 = (-(single_i32_from_vec as i64)) as u64;
+            vec_of_u16s.push(access_value(single_value, 5, 5).try_into().unwrap());
+        } else {
+            let pair_value: u64 = single_i32_from_vec as u64;
+            vec_of_u16s.push(access_value(pair_value, 10, 5).try_into().unwrap());
+            vec_of_u16s.push(access_value(pair_value, 5, 5).try_into().unwrap());
+        }
+    }
+    vec_of_u16s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_u16_to_i32_pairs() {
+        let v: Vec<i32> = serialize_u16_to_i32(vec![12, 65535, 21474, 9]);
+        assert_eq!(v, vec![1265535, 2147400009]);
+    }
+
+    #[test]
+    fn test_serialize_u16_to_i32_large_leading_value() {
+        // 21475 can not lead a pair so it is stored alone, then 7 and 8 pair up
+        let v: Vec<i32> = serialize_u16_to_i32(vec![21475, 7, 8]);
+        assert_eq!(v, vec![-121475, 700008]);
+    }
+
+    #[test]
+    fn test_serialize_u16_to_i32_odd_tail() {
+        let v: Vec<i32> = serialize_u16_to_i32(vec![1, 2, 65535]);
+        assert_eq!(v, vec![100002, -165535]);
+    }
+
+    #[test]
+    fn test_deserialize_i32_to_u16() {
+        let v: Vec<u16> = deserialize_i32_to_u16(vec![-121475, 700008, -100000]);
+        assert_eq!(v, vec![21475, 7, 8, 0]);
+    }
+
+    #[test]
+    fn test_round_trip_every_u16() {
+        let all: Vec<u16> = (0..=u16::MAX).collect();
+        let encoded: Vec<i32> = serialize_u16_to_i32(all.clone());
+        assert_eq!(deserialize_i32_to_u16(encoded), all);
+        // And every value again paired with its reverse, to exercise both halves of a pair word
+        let mixed: Vec<u16> = (0..=u16::MAX).flat_map(|i| vec![i, u16::MAX - i]).collect();
+        let encoded: Vec<i32> = serialize_u16_to_i32(mixed.clone());
+        assert_eq!(deserialize_i32_to_u16(encoded), mixed);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(serialize_u16_to_i32(Vec::new()).is_empty());
+        assert!(deserialize_i32_to_u16(Vec::new()).is_empty());
+    }
+}
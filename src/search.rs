@@ -0,0 +1,156 @@
+//! Searching for byte patterns directly in a `serialize_u8_to_i32` vector, without decoding it first.
+//!
+//! The bytes are read one digit group at a time (see `view`), so a needle straddling word boundaries is found like
+//! any other. Matching is Knuth-Morris-Pratt, which never steps back in the encoded words. Offsets are byte offsets
+//! into what `deserialize_i32_to_u8` would return, and like `str::match_indices` the matches do not overlap.
+//!
+//! The words must be well formed i.e. as produced by `serialize_u8_to_i32` or held by `EncodedBytes`: searching
+//! panics when it reaches a malformed word (check untrusted vectors with `validate` first).
+use crate::view::{byte_at, encoded_len};
+
+// For every prefix of the needle, the length of its longest proper prefix which is also a suffix of it
+fn failure_table(needle: &[u8]) -> Vec<usize> {
+    let mut table: Vec<usize> = vec![0; needle.len()];
+    let mut matched: usize = 0;
+    for i in 1..needle.len() {
+        while matched > 0 && needle[i] != needle[matched] {
+            matched = table[matched - 1];
+        }
+        if needle[i] == needle[matched] {
+            matched += 1;
+        }
+        table[i] = matched;
+    }
+    table
+}
+
+pub struct FindIter<'a, 'b> {
+    i32_data: &'a [i32],
+    needle: &'b [u8],
+    table: Vec<usize>,
+    len: usize,
+    // The next byte to read
+    position: usize,
+    // How many bytes of the needle the bytes before position end with
+    matched: usize,
+}
+
+impl<'a, 'b> Iterator for FindIter<'a, 'b> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.needle.is_empty() {
+            // An empty needle matches at every offset, the end included
+            if self.position > self.len {
+                return None;
+            }
+            self.position += 1;
+            return Some(self.position - 1);
+        }
+        while self.position < self.len {
+            let byte: u8 = byte_at(self.i32_data, self.position);
+            self.position += 1;
+            while self.matched > 0 && byte != self.needle[self.matched] {
+                self.matched = self.table[self.matched - 1];
+            }
+            if byte == self.needle[self.matched] {
+                self.matched += 1;
+            }
+            if self.matched == self.needle.len() {
+                // Start afresh after a match, so that matches do not overlap
+                self.matched = 0;
+                return Some(self.position - self.needle.len());
+            }
+        }
+        None
+    }
+}
+
+pub fn find_iter<'a, 'b>(i32_data: &'a [i32], needle: &'b [u8]) -> FindIter<'a, 'b> {
+    FindIter {
+        i32_data,
+        needle,
+        table: failure_table(needle),
+        len: encoded_len(i32_data),
+        position: 0,
+        matched: 0,
+    }
+}
+
+pub fn find(i32_data: &[i32], needle: &[u8]) -> Option<usize> {
+    find_iter(i32_data, needle).next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s_d_u8_i32;
+    use crate::test_util::XorShift;
+
+    // The offsets found by searching the decoded bytes the slow way
+    fn naive_find_all(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+        if needle.is_empty() {
+            return (0..=haystack.len()).collect();
+        }
+        let mut offsets: Vec<usize> = Vec::new();
+        let mut start: usize = 0;
+        while start + needle.len() <= haystack.len() {
+            if haystack[start..start + needle.len()] == *needle {
+                offsets.push(start);
+                start += needle.len();
+            } else {
+                start += 1;
+            }
+        }
+        offsets
+    }
+
+    #[test]
+    fn test_find() {
+        let v: Vec<i32> = s_d_u8_i32::serialize_u8_to_i32(b"GIF89a header".to_vec());
+        assert_eq!(find(&v, b"GIF"), Some(0));
+        // Straddles the first and second words
+        assert_eq!(find(&v, b"89a"), Some(3));
+        assert_eq!(find(&v, b"F8"), Some(2));
+        // Ends in the tail word
+        assert_eq!(find(&v, b"der"), Some(10));
+        assert_eq!(find(&v, b"PNG"), None);
+        assert_eq!(find(&v, b"GIF89a header!"), None);
+        assert_eq!(find(&[], b"a"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "word 0 can not be decoded")]
+    fn test_find_malformed_word() {
+        find(&[1999000000], b"a");
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let v: Vec<i32> = s_d_u8_i32::serialize_u8_to_i32(b"aaaaa abab".to_vec());
+        assert_eq!(find_iter(&v, b"aa").collect::<Vec<usize>>(), vec![0, 2]);
+        assert_eq!(find_iter(&v, b"ab").collect::<Vec<usize>>(), vec![6, 8]);
+        assert_eq!(
+            find_iter(&v[..1], b"").collect::<Vec<usize>>(),
+            vec![0, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_matches_naive_search() {
+        let mut rng = XorShift::new(1);
+        // Two byte values make partial matches, and so KMP fallbacks, very common
+        let alphabet: &[u8] = &[0, 1];
+        for length in 0..60 {
+            let haystack: Vec<u8> = rng.bytes_from(alphabet, length);
+            let v: Vec<i32> = s_d_u8_i32::serialize_u8_to_i32(haystack.clone());
+            for needle_length in 0..6 {
+                let needle: Vec<u8> = rng.bytes_from(alphabet, needle_length);
+                assert_eq!(
+                    find_iter(&v, &needle).collect::<Vec<usize>>(),
+                    naive_find_all(&haystack, &needle)
+                );
+            }
+        }
+    }
+}
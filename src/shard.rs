@@ -0,0 +1,308 @@
+//! Storing byte streams which are too large for a single i32 vector as a number of shards plus a manifest.
+//!
+//! Each shard is an ordinary `serialize_u8_to_i32` vector (so it can be decoded on its own with `deserialize_i32_to_u8`)
+//! and the manifest is a plain i32 vector which starts with the number of shards, followed by the storage key,
+//! byte length and checksum of each shard in order i.e. `[2, 7, 3000, 123456, 8, 1200, 654321]`.
+use crate::checksum::checksum_u8;
+use crate::s_d_u8_i32;
+use crate::storage::Storage;
+use crate::validate::first_invalid_word;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::thread;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShardInfo {
+    pub key: i32,
+    pub length: i32,
+    pub checksum: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShardError {
+    MalformedManifest,
+    LengthMismatch {
+        index: usize,
+        expected: i32,
+        actual: usize,
+    },
+    ChecksumMismatch {
+        index: usize,
+        expected: i32,
+        actual: i32,
+    },
+    // A stored word deserialize_i32_to_u8 can not decode, by its index in the shard
+    InvalidWord {
+        index: usize,
+        word_index: usize,
+    },
+}
+
+impl fmt::Display for ShardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShardError::MalformedManifest => write!(f, "malformed shard manifest"),
+            ShardError::LengthMismatch {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "shard {} holds {} bytes, the manifest expects {}",
+                index, actual, expected
+            ),
+            ShardError::ChecksumMismatch {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "shard {} has checksum {}, the manifest expects {}",
+                index, actual, expected
+            ),
+            ShardError::InvalidWord { index, word_index } => {
+                write!(f, "shard {} word {} can not be decoded", index, word_index)
+            }
+        }
+    }
+}
+
+impl Error for ShardError {}
+
+pub fn encode_manifest(shards: &[ShardInfo]) -> Vec<i32> {
+    let mut manifest: Vec<i32> = vec![shards.len().try_into().unwrap()];
+    for shard in shards {
+        manifest.push(shard.key);
+        manifest.push(shard.length);
+        manifest.push(shard.checksum);
+    }
+    manifest
+}
+
+pub fn decode_manifest(manifest: &[i32]) -> Result<Vec<ShardInfo>, ShardError> {
+    let (count, entries) = manifest
+        .split_first()
+        .ok_or(ShardError::MalformedManifest)?;
+    if *count < 0 || entries.len() != *count as usize * 3 {
+        return Err(ShardError::MalformedManifest);
+    }
+    let mut shards: Vec<ShardInfo> = Vec::new();
+    for entry in entries.chunks(3) {
+        if entry[1] < 0 {
+            return Err(ShardError::MalformedManifest);
+        }
+        shards.push(ShardInfo {
+            key: entry[0],
+            length: entry[1],
+            checksum: entry[2],
+        });
+    }
+    Ok(shards)
+}
+
+// Splits the bytes into shards of at most shard_size bytes, stores each one and then the manifest, returning the manifest's key
+pub fn store_sharded<S: Storage + ?Sized>(
+    storage: &mut S,
+    u8_data: &[u8],
+    shard_size: usize,
+) -> i32 {
+    assert!(
+        shard_size > 0 && shard_size <= i32::MAX as usize,
+        "shard size must be between 1 and i32::MAX bytes"
+    );
+    let mut shards: Vec<ShardInfo> = Vec::new();
+    for shard_data in u8_data.chunks(shard_size) {
+        let key: i32 = storage.store(&s_d_u8_i32::serialize_u8_to_i32(shard_data.to_vec()));
+        shards.push(ShardInfo {
+            key,
+            length: shard_data.len().try_into().unwrap(),
+            checksum: checksum_u8(shard_data),
+        });
+    }
+    storage.store(&encode_manifest(&shards))
+}
+
+fn load_shard<S: Storage + ?Sized>(
+    storage: &S,
+    index: usize,
+    shard: &ShardInfo,
+) -> Result<Vec<u8>, ShardError> {
+    let shard_words: Vec<i32> = storage.load(shard.key);
+    if let Some(word_index) = first_invalid_word(&shard_words) {
+        return Err(ShardError::InvalidWord { index, word_index });
+    }
+    let shard_data: Vec<u8> = s_d_u8_i32::deserialize_i32_to_u8(shard_words);
+    if shard_data.len() != shard.length as usize {
+        return Err(ShardError::LengthMismatch {
+            index,
+            expected: shard.length,
+            actual: shard_data.len(),
+        });
+    }
+    let checksum: i32 = checksum_u8(&shard_data);
+    if checksum != shard.checksum {
+        return Err(ShardError::ChecksumMismatch {
+            index,
+            expected: shard.checksum,
+            actual: checksum,
+        });
+    }
+    Ok(shard_data)
+}
+
+pub fn load_sharded<S: Storage + ?Sized>(
+    storage: &S,
+    manifest_key: i32,
+) -> Result<Vec<u8>, ShardError> {
+    let shards: Vec<ShardInfo> = decode_manifest(&storage.load(manifest_key))?;
+    let mut u8_data: Vec<u8> = Vec::new();
+    for (index, shard) in shards.iter().enumerate() {
+        u8_data.extend(load_shard(storage, index, shard)?);
+    }
+    Ok(u8_data)
+}
+
+// The same as load_sharded but spreads the loading and decoding of the shards over a number of threads
+pub fn load_sharded_parallel<S: Storage + Sync + ?Sized>(
+    storage: &S,
+    manifest_key: i32,
+    threads: usize,
+) -> Result<Vec<u8>, ShardError> {
+    let shards: Vec<ShardInfo> = decode_manifest(&storage.load(manifest_key))?;
+    if shards.is_empty() {
+        return Ok(Vec::new());
+    }
+    let shards_per_thread: usize = shards.len().div_ceil(threads.max(1));
+    let batches: Vec<Result<Vec<Vec<u8>>, ShardError>> = thread::scope(|scope| {
+        let handles: Vec<_> = shards
+            .chunks(shards_per_thread)
+            .enumerate()
+            .map(|(batch, batch_shards)| {
+                scope.spawn(move || {
+                    batch_shards
+                        .iter()
+                        .enumerate()
+                        .map(|(i, shard)| load_shard(storage, batch * shards_per_thread + i, shard))
+                        .collect()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    let mut u8_data: Vec<u8> = Vec::new();
+    for batch in batches {
+        for shard_data in batch? {
+            u8_data.extend(shard_data);
+        }
+    }
+    Ok(u8_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn test_data(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_store_and_load_sharded() {
+        let mut storage = MemoryStorage::new();
+        let data: Vec<u8> = test_data(1000);
+        let manifest_key: i32 = store_sharded(&mut storage, &data, 64);
+        let shards: Vec<ShardInfo> = decode_manifest(&storage.load(manifest_key)).unwrap();
+        assert_eq!(shards.len(), 16);
+        assert_eq!(shards[15].length, 1000 - 15 * 64);
+        assert_eq!(load_sharded(&storage, manifest_key).unwrap(), data);
+        assert_eq!(
+            load_sharded_parallel(&storage, manifest_key, 4).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_each_shard_decodes_on_its_own() {
+        let mut storage = MemoryStorage::new();
+        let data: Vec<u8> = test_data(100);
+        let manifest_key: i32 = store_sharded(&mut storage, &data, 30);
+        let shards: Vec<ShardInfo> = decode_manifest(&storage.load(manifest_key)).unwrap();
+        let second: Vec<u8> = s_d_u8_i32::deserialize_i32_to_u8(storage.load(shards[1].key));
+        assert_eq!(second, data[30..60].to_vec());
+    }
+
+    #[test]
+    fn test_empty_blob() {
+        let mut storage = MemoryStorage::new();
+        let manifest_key: i32 = store_sharded(&mut storage, &[], 64);
+        assert_eq!(storage.load(manifest_key), vec![0]);
+        assert_eq!(
+            load_sharded(&storage, manifest_key).unwrap(),
+            Vec::<u8>::new()
+        );
+        assert_eq!(
+            load_sharded_parallel(&storage, manifest_key, 4).unwrap(),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn test_corrupt_shard() {
+        let mut storage = MemoryStorage::new();
+        let data: Vec<u8> = test_data(90);
+        let manifest_key: i32 = store_sharded(&mut storage, &data, 30);
+        let shards: Vec<ShardInfo> = decode_manifest(&storage.load(manifest_key)).unwrap();
+        // Swap the second shard for one with a single different byte
+        let mut tampered: Vec<u8> = data[30..60].to_vec();
+        tampered[0] ^= 1;
+        storage.delete(shards[1].key);
+        let tampered_key: i32 = storage.store(&s_d_u8_i32::serialize_u8_to_i32(tampered));
+        let mut tampered_shards: Vec<ShardInfo> = shards.clone();
+        tampered_shards[1].key = tampered_key;
+        let tampered_manifest: i32 = storage.store(&encode_manifest(&tampered_shards));
+        match load_sharded(&storage, tampered_manifest) {
+            Err(ShardError::ChecksumMismatch { index: 1, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        // The original shard is gone so the original manifest now fails on length
+        match load_sharded_parallel(&storage, manifest_key, 2) {
+            Err(ShardError::LengthMismatch {
+                index: 1,
+                expected: 30,
+                actual: 0,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_undecodable_shard() {
+        let mut storage = MemoryStorage::new();
+        let data: Vec<u8> = test_data(90);
+        let manifest_key: i32 = store_sharded(&mut storage, &data, 30);
+        let shards: Vec<ShardInfo> = decode_manifest(&storage.load(manifest_key)).unwrap();
+        let mut words: Vec<i32> = storage.load(shards[2].key);
+        words[3] = 1999000000;
+        storage.update(shards[2].key, &words);
+        let expected = Err(ShardError::InvalidWord {
+            index: 2,
+            word_index: 3,
+        });
+        assert_eq!(load_sharded(&storage, manifest_key), expected);
+        assert_eq!(load_sharded_parallel(&storage, manifest_key, 2), expected);
+    }
+
+    #[test]
+    fn test_malformed_manifest() {
+        assert_eq!(decode_manifest(&[]), Err(ShardError::MalformedManifest));
+        assert_eq!(
+            decode_manifest(&[2, 1, 3, 4]),
+            Err(ShardError::MalformedManifest)
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! A storage abstraction modelled on the SSVM storage interface (`store_as_i32_vector` / `load_as_i32_vector`).
+//!
+//! Code written against the `Storage` trait can run on the real host in production and on `MemoryStorage` or
+//! `DirectoryStorage` locally, so pipelines like `solarize_the_pixels` can be unit tested without the VM.
+use crate::s_d_u8_i32;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub trait Storage {
+    // Stores the vector and returns the new storage key
+    fn store(&mut self, i32_data: &[i32]) -> i32;
+    // Loads the vector stored under the key, an unknown key loads as an empty vector
+    fn load(&self, key: i32) -> Vec<i32>;
+    // Replaces the vector stored under the key, updating an unknown key stores the vector under that key
+    fn update(&mut self, key: i32, i32_data: &[i32]);
+    // Removes the vector stored under the key, deleting an unknown key does nothing
+    fn delete(&mut self, key: i32);
+}
+
+// Serializes the bytes to i32 and stores them, returning the storage key
+pub fn store_bytes<S: Storage + ?Sized>(storage: &mut S, u8_data: Vec<u8>) -> i32 {
+    storage.store(&s_d_u8_i32::serialize_u8_to_i32(u8_data))
+}
+
+// Loads the i32 vector stored under the key and deserializes it back to bytes
+pub fn load_bytes<S: Storage + ?Sized>(storage: &S, key: i32) -> Vec<u8> {
+    s_d_u8_i32::deserialize_i32_to_u8(storage.load(key))
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    vectors: HashMap<i32, Vec<i32>>,
+    last_key: i32,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    pub fn contains_key(&self, key: i32) -> bool {
+        self.vectors.contains_key(&key)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn store(&mut self, i32_data: &[i32]) -> i32 {
+        self.last_key += 1;
+        self.vectors.insert(self.last_key, i32_data.to_vec());
+        self.last_key
+    }
+
+    fn load(&self, key: i32) -> Vec<i32> {
+        self.vectors.get(&key).cloned().unwrap_or_default()
+    }
+
+    fn update(&mut self, key: i32, i32_data: &[i32]) {
+        // Keys handed out by store must stay unique
+        self.last_key = self.last_key.max(key);
+        self.vectors.insert(key, i32_data.to_vec());
+    }
+
+    fn delete(&mut self, key: i32) {
+        self.vectors.remove(&key);
+    }
+}
+
+// Stores each vector as a `<key>.i32` file in a directory, one decimal i32 per line
+#[derive(Debug)]
+pub struct DirectoryStorage {
+    path: PathBuf,
+    last_key: i32,
+}
+
+impl DirectoryStorage {
+    // Opens (creating if necessary) the directory, new keys carry on from the highest key already stored there
+    pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<DirectoryStorage> {
+        let path: PathBuf = path.into();
+        fs::create_dir_all(&path)?;
+        let mut last_key: i32 = 0;
+        for entry in fs::read_dir(&path)? {
+            let file_name = entry?.file_name();
+            if let Some(key) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".i32"))
+                .and_then(|key| key.parse::<i32>().ok())
+            {
+                last_key = last_key.max(key);
+            }
+        }
+        Ok(DirectoryStorage { path, last_key })
+    }
+
+    fn file_path(&self, key: i32) -> PathBuf {
+        self.path.join(format!("{}.i32", key))
+    }
+
+    fn write_file(&self, key: i32, i32_data: &[i32]) {
+        let mut contents: String = String::new();
+        for single_i32 in i32_data {
+            contents.push_str(&single_i32.to_string());
+            contents.push('\n');
+        }
+        fs::write(self.file_path(key), contents).expect("unable to write storage file");
+    }
+}
+
+impl Storage for DirectoryStorage {
+    fn store(&mut self, i32_data: &[i32]) -> i32 {
+        self.last_key += 1;
+        self.write_file(self.last_key, i32_data);
+        self.last_key
+    }
+
+    fn load(&self, key: i32) -> Vec<i32> {
+        match fs::read_to_string(self.file_path(key)) {
+            Ok(contents) => contents
+                .lines()
+                .map(|line| line.parse::<i32>().expect("corrupt storage file"))
+                .collect(),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => panic!("unable to read storage file: {}", e),
+        }
+    }
+
+    fn update(&mut self, key: i32, i32_data: &[i32]) {
+        self.last_key = self.last_key.max(key);
+        self.write_file(key, i32_data);
+    }
+
+    fn delete(&mut self, key: i32) {
+        match fs::remove_file(self.file_path(key)) {
+            Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
+                panic!("unable to delete storage file: {}", e)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The solarize example from the crate documentation, written against the Storage trait
+    fn solarize_the_pixels<S: Storage>(storage: &mut S, _orig_image_location: i32) -> i32 {
+        let mut individual_pixels: Vec<u8> = load_bytes(storage, _orig_image_location);
+        for pixel in individual_pixels.iter_mut() {
+            if 200 - *pixel as i32 > 0 {
+                *pixel = 200 - *pixel;
+            }
+        }
+        store_bytes(storage, individual_pixels)
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        let mut storage = MemoryStorage::new();
+        let key: i32 = storage.store(&[1009010011, 2000015016]);
+        assert_eq!(storage.load(key), vec![1009010011, 2000015016]);
+        assert_eq!(storage.load(key + 1), Vec::<i32>::new());
+        storage.update(key, &[12]);
+        assert_eq!(storage.load(key), vec![12]);
+        storage.update(5, &[13]);
+        assert_eq!(storage.store(&[]), 6);
+        storage.delete(5);
+        storage.delete(6);
+        storage.delete(key);
+        assert!(storage.is_empty());
+        assert_eq!(storage.load(key), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_solarize_the_pixels() {
+        let mut storage = MemoryStorage::new();
+        let original: i32 = store_bytes(&mut storage, vec![134, 122, 255, 0, 200, 199, 1]);
+        let solarized: i32 = solarize_the_pixels(&mut storage, original);
+        assert_ne!(original, solarized);
+        assert_eq!(
+            load_bytes(&storage, solarized),
+            vec![66, 78, 255, 200, 200, 1, 199]
+        );
+        assert_eq!(storage.len(), 2);
+    }
+
+    #[test]
+    fn test_directory_storage() {
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "serialize_deserialize_u8_i32_storage_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        let mut storage = DirectoryStorage::new(&path).unwrap();
+        let first: i32 = store_bytes(&mut storage, vec![9, 10, 11, 12, 13, 14, 15, 16]);
+        let second: i32 = storage.store(&[]);
+        assert_eq!(
+            load_bytes(&storage, first),
+            vec![9, 10, 11, 12, 13, 14, 15, 16]
+        );
+        assert_eq!(storage.load(second), Vec::<i32>::new());
+        // Re-opening the directory carries on from the existing keys
+        let mut reopened = DirectoryStorage::new(&path).unwrap();
+        let third: i32 = reopened.store(&[1001002003]);
+        assert!(third > second);
+        reopened.update(second, &[1004005006]);
+        assert_eq!(reopened.load(second), vec![1004005006]);
+        reopened.delete(first);
+        reopened.delete(first);
+        assert_eq!(reopened.load(first), Vec::<i32>::new());
+        assert_eq!(reopened.load(third), vec![1001002003]);
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! A tag-length-value record stream over i32 words, for appending heterogeneous records (event logs etc.) to one vector.
+//!
+//! Each record is a tag word, a length word holding the payload's length in bytes, and then the payload packed with
+//! `serialize_u8_to_i32` i.e. tag 7 with the payload `[1, 2, 3, 4]` is `[7, 4, 1001002003, 4]`. Because the number of
+//! payload words follows from the length, readers can skip records with tags they do not know without decoding them.
+use crate::s_d_u8_i32;
+use crate::validate::first_invalid_word;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub tag: i32,
+    // Index of the record's tag word
+    pub offset: usize,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TlvError {
+    // The record starting at offset runs past the end of the vector
+    Truncated { offset: usize },
+    // The record starting at offset has a negative length
+    InvalidLength { offset: usize },
+    // The payload of the record starting at offset does not decode to its length in bytes, the record's extent is
+    // still known so reading carries on with the next record
+    InvalidPayload { offset: usize },
+}
+
+impl fmt::Display for TlvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlvError::Truncated { offset } => write!(f, "record at word {} is truncated", offset),
+            TlvError::InvalidLength { offset } => {
+                write!(f, "record at word {} has an invalid length", offset)
+            }
+            TlvError::InvalidPayload { offset } => {
+                write!(f, "record at word {} has a corrupt payload", offset)
+            }
+        }
+    }
+}
+
+impl Error for TlvError {}
+
+pub fn append_record(i32_data: &mut Vec<i32>, tag: i32, payload: &[u8]) {
+    i32_data.push(tag);
+    i32_data.push(payload.len().try_into().unwrap());
+    i32_data.extend(s_d_u8_i32::serialize_u8_to_i32(payload.to_vec()));
+}
+
+pub fn records(i32_data: &[i32]) -> Records<'_> {
+    Records {
+        i32_data,
+        position: 0,
+        known_tags: None,
+        skipped: 0,
+    }
+}
+
+pub struct Records<'a> {
+    i32_data: &'a [i32],
+    position: usize,
+    known_tags: Option<&'a [i32]>,
+    skipped: usize,
+}
+
+impl<'a> Records<'a> {
+    // Only yield records with these tags, any others are stepped over without being decoded
+    pub fn known_tags(mut self, tags: &'a [i32]) -> Records<'a> {
+        self.known_tags = Some(tags);
+        self
+    }
+
+    // How many records with unknown tags have been skipped so far
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    fn fail(&mut self, error: TlvError) -> Option<Result<Record, TlvError>> {
+        // Nothing after a broken record can be trusted, so stop here
+        self.position = self.i32_data.len();
+        Some(Err(error))
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<Record, TlvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.i32_data.len() {
+            let offset: usize = self.position;
+            let tag: i32 = self.i32_data[offset];
+            let length: usize = match self.i32_data.get(offset + 1) {
+                None => return self.fail(TlvError::Truncated { offset }),
+                Some(length) => match (*length).try_into() {
+                    Ok(length) => length,
+                    Err(_) => return self.fail(TlvError::InvalidLength { offset }),
+                },
+            };
+            let end: usize = offset + 2 + length.div_ceil(3);
+            if end > self.i32_data.len() {
+                return self.fail(TlvError::Truncated { offset });
+            }
+            self.position = end;
+            if let Some(tags) = self.known_tags {
+                if !tags.contains(&tag) {
+                    self.skipped += 1;
+                    continue;
+                }
+            }
+            let payload_words: &[i32] = &self.i32_data[offset + 2..end];
+            if first_invalid_word(payload_words).is_some() {
+                return Some(Err(TlvError::InvalidPayload { offset }));
+            }
+            let payload: Vec<u8> = s_d_u8_i32::deserialize_i32_to_u8(payload_words.to_vec());
+            if payload.len() != length {
+                return Some(Err(TlvError::InvalidPayload { offset }));
+            }
+            return Some(Ok(Record {
+                tag,
+                offset,
+                payload,
+            }));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_log() -> Vec<i32> {
+        let mut log: Vec<i32> = Vec::new();
+        append_record(&mut log, 7, &[1, 2, 3, 4]);
+        append_record(&mut log, 99, b"from a newer writer");
+        append_record(&mut log, 8, &[]);
+        append_record(&mut log, 7, &[255]);
+        log
+    }
+
+    #[test]
+    fn test_append_record() {
+        let mut log: Vec<i32> = Vec::new();
+        append_record(&mut log, 7, &[1, 2, 3, 4]);
+        assert_eq!(log, vec![7, 4, 1001002003, 4]);
+    }
+
+    #[test]
+    fn test_walk_records() {
+        let log: Vec<i32> = test_log();
+        let all: Vec<Record> = records(&log).map(|record| record.unwrap()).collect();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[1].payload, b"from a newer writer".to_vec());
+        assert_eq!(all[2].offset, 13);
+        assert_eq!(all[2].payload, Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_skip_unknown_tags() {
+        let log: Vec<i32> = test_log();
+        let mut iter = records(&log).known_tags(&[7, 8]);
+        let tags: Vec<i32> = iter.by_ref().map(|record| record.unwrap().tag).collect();
+        assert_eq!(tags, vec![7, 8, 7]);
+        assert_eq!(iter.skipped(), 1);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let log: Vec<i32> = test_log();
+        let truncated: &[i32] = &log[..log.len() - 1];
+        let results: Vec<Result<Record, TlvError>> = records(truncated).collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[3], Err(TlvError::Truncated { offset: 15 }));
+        // A lone tag word is truncated too
+        assert_eq!(
+            records(&[7]).collect::<Vec<_>>(),
+            vec![Err(TlvError::Truncated { offset: 0 })]
+        );
+        assert_eq!(
+            records(&[7, -1, 0]).collect::<Vec<_>>(),
+            vec![Err(TlvError::InvalidLength { offset: 0 })]
+        );
+    }
+
+    #[test]
+    fn test_corrupt_payload() {
+        let mut iter = records(&[7, 3, 1999999999]);
+        assert_eq!(
+            iter.next(),
+            Some(Err(TlvError::InvalidPayload { offset: 0 }))
+        );
+        assert_eq!(iter.next(), None);
+        // The record after a corrupt payload is still read
+        let mut log: Vec<i32> = test_log();
+        log[2] = 2000001002;
+        let results: Vec<Result<Record, TlvError>> = records(&log).collect();
+        assert_eq!(results[0], Err(TlvError::InvalidPayload { offset: 0 }));
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[1].as_ref().unwrap().tag, 99);
+    }
+}
@@ -0,0 +1,222 @@
+//! Strict validation of `serialize_u8_to_i32` vectors.
+//!
+//! `deserialize_i32_to_u8` accepts words the encoder never produces: digit groups above 255 (which make it panic),
+//! `2` prefixed or `0` prefixed words with stray high digits (which it silently ignores) and tail words in the
+//! middle of a stream. `validate` lists every such word so that storage ingest can insist on exactly one
+//! representation for each byte string.
+use crate::s_d_u8_i32::access_value;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Severity {
+    // deserialize_i32_to_u8 can not decode the word (it would panic or produce nonsense)
+    Invalid,
+    // The word decodes, but serialize_u8_to_i32 would never have written it like this
+    NonCanonical,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IssueReason {
+    Negative,
+    // A digit group (identified by its digit position, as used by access_value) holds more than 255
+    GroupOutOfRange { position: u64 },
+    // A tail word has non-zero digits above the groups it holds
+    NonZeroHighGroup,
+    // A tail word (mode 0 or 2) which is not the last word
+    TailNotLast,
+}
+
+impl IssueReason {
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueReason::Negative | IssueReason::GroupOutOfRange { .. } => Severity::Invalid,
+            IssueReason::NonZeroHighGroup | IssueReason::TailNotLast => Severity::NonCanonical,
+        }
+    }
+}
+
+impl fmt::Display for IssueReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IssueReason::Negative => write!(f, "negative word"),
+            IssueReason::GroupOutOfRange { position } => {
+                write!(f, "digit group at position {} is above 255", position)
+            }
+            IssueReason::NonZeroHighGroup => write!(f, "tail word has non-zero high digits"),
+            IssueReason::TailNotLast => write!(f, "tail word is not the last word"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ValidationIssue {
+    pub index: usize,
+    pub word: i32,
+    pub reason: IssueReason,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    // No issues at all, the vector is exactly what serialize_u8_to_i32 produces for its bytes
+    pub fn is_canonical(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    // Every word can be decoded, although some may not be canonical
+    pub fn is_decodable(&self) -> bool {
+        self.issues
+            .iter()
+            .all(|issue| issue.reason.severity() != Severity::Invalid)
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.issues.is_empty() {
+            return write!(f, "no issues");
+        }
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "word {} ({}): {}", issue.index, issue.word, issue.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationReport {}
+
+// The mode of a non-negative word, 1 for full words and 2 or 0 for tail words
+pub fn word_mode(_word: i32) -> u64 {
+    access_value(_word as u64, 10, 1)
+}
+
+pub fn is_tail_word(_word: i32) -> bool {
+    _word >= 0 && word_mode(_word) != 1
+}
+
+// Everything wrong with a single word, regardless of where it sits in the vector
+pub fn check_word(_word: i32) -> Vec<IssueReason> {
+    let mut reasons: Vec<IssueReason> = Vec::new();
+    if _word < 0 {
+        reasons.push(IssueReason::Negative);
+        return reasons;
+    }
+    let value: u64 = _word as u64;
+    // The groups each mode holds, the rest of the digits below the mode digit must be zero
+    let (groups, high_digits): (&[u64], u64) = match word_mode(_word) {
+        1 => (&[9, 6, 3], 0),
+        2 => (&[6, 3], access_value(value, 9, 3)),
+        _ => (&[3], access_value(value, 9, 6)),
+    };
+    if high_digits != 0 {
+        reasons.push(IssueReason::NonZeroHighGroup);
+    }
+    for position in groups {
+        if access_value(value, *position, 3) > 255 {
+            reasons.push(IssueReason::GroupOutOfRange {
+                position: *position,
+            });
+        }
+    }
+    reasons
+}
+
+// The index of the first word deserialize_i32_to_u8 can not decode, so callers can check before decoding
+pub fn first_invalid_word(i32_data: &[i32]) -> Option<usize> {
+    i32_data.iter().position(|word| {
+        check_word(*word)
+            .iter()
+            .any(|reason| reason.severity() == Severity::Invalid)
+    })
+}
+
+pub fn validate(i32_data: &[i32]) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    for (index, word) in i32_data.iter().enumerate() {
+        for reason in check_word(*word) {
+            report.issues.push(ValidationIssue {
+                index,
+                word: *word,
+                reason,
+            });
+        }
+        if is_tail_word(*word) && index + 1 != i32_data.len() {
+            report.issues.push(ValidationIssue {
+                index,
+                word: *word,
+                reason: IssueReason::TailNotLast,
+            });
+        }
+    }
+    report
+}
+
+pub fn is_canonical(i32_data: &[i32]) -> bool {
+    i32_data.iter().enumerate().all(|(index, word)| {
+        check_word(*word).is_empty() && (!is_tail_word(*word) || index + 1 == i32_data.len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s_d_u8_i32;
+
+    #[test]
+    fn test_serialized_output_is_canonical() {
+        for length in 0..10 {
+            let data: Vec<u8> = (0..length).map(|i| (i * 71) as u8).collect();
+            let v: Vec<i32> = s_d_u8_i32::serialize_u8_to_i32(data);
+            assert!(is_canonical(&v));
+            assert!(validate(&v).is_canonical());
+        }
+    }
+
+    #[test]
+    fn test_validate_lists_every_issue() {
+        let v: Vec<i32> = vec![1009010011, 2000015016, 1256010999, -5, 2001015016, 1005, 7];
+        let report: ValidationReport = validate(&v);
+        let issues: Vec<(usize, IssueReason)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.index, issue.reason))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (1, IssueReason::TailNotLast),
+                (2, IssueReason::GroupOutOfRange { position: 9 }),
+                (2, IssueReason::GroupOutOfRange { position: 3 }),
+                (3, IssueReason::Negative),
+                (4, IssueReason::NonZeroHighGroup),
+                (4, IssueReason::TailNotLast),
+                (5, IssueReason::NonZeroHighGroup),
+                (5, IssueReason::TailNotLast),
+            ]
+        );
+        assert!(!report.is_decodable());
+        assert!(!is_canonical(&v));
+        assert_eq!(first_invalid_word(&v), Some(2));
+        assert_eq!(first_invalid_word(&v[..2]), None);
+    }
+
+    #[test]
+    fn test_non_canonical_but_decodable() {
+        // Two vectors concatenated, the first ending in a tail word
+        let v: Vec<i32> = vec![2000015016, 1009010011];
+        let report: ValidationReport = validate(&v);
+        assert!(report.is_decodable());
+        assert!(!report.is_canonical());
+        assert_eq!(
+            report.to_string(),
+            "word 0 (2000015016): tail word is not the last word"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Random access to, and in place mutation of, the bytes inside a `serialize_u8_to_i32` vector without decoding the whole thing.
+//!
+//! Every word apart from the last holds exactly three bytes, so byte `i` lives in word `i / 3` and only that word
+//! needs to be read. The last word may be a tail word (mode 2 holding two bytes, or mode 0 holding one byte).
+use crate::s_d_u8_i32::{access_value, insert_value_at_position};
+use std::convert::TryInto;
+use std::ops::{Bound, Range, RangeBounds};
+
+// The number of bytes a single word holds, going by its mode
+pub fn bytes_in_word(_word: i32) -> usize {
+    if (0..=255).contains(&_word) {
+        return 1;
+    }
+    match access_value(_word as u64, 10, 1) {
+        1 => 3,
+        2 => 2,
+        _ => 1,
+    }
+}
+
+// The digit position (as used by access_value and insert_value_at_position) of a byte inside a word holding byte_count bytes
+pub fn byte_position(byte_count: usize, index_in_word: usize) -> u64 {
+    (3 * (byte_count - index_in_word)) as u64
+}
+
+// Total number of bytes in a well formed vector, only the last word is inspected
+pub fn encoded_len(i32_data: &[i32]) -> usize {
+    match i32_data.last() {
+        Some(last) => 3 * (i32_data.len() - 1) + bytes_in_word(*last),
+        None => 0,
+    }
+}
+
+// Turns any range over bytes into start..end, panicking if it does not fit inside len like slice indexing does
+pub(crate) fn resolve_range<R: RangeBounds<usize>>(range: R, len: usize) -> Range<usize> {
+    let start: usize = match range.start_bound() {
+        Bound::Included(start) => *start,
+        Bound::Excluded(start) => start + 1,
+        Bound::Unbounded => 0,
+    };
+    let end: usize = match range.end_bound() {
+        Bound::Included(end) => end + 1,
+        Bound::Excluded(end) => *end,
+        Bound::Unbounded => len,
+    };
+    assert!(
+        start <= end && end <= len,
+        "range {}..{} out of bounds for {} bytes",
+        start,
+        end,
+        len
+    );
+    start..end
+}
+
+// None when the word holding the byte is negative or the byte's digit group is above 255, which
+// serialize_u8_to_i32 never writes
+pub(crate) fn checked_byte_at(i32_data: &[i32], index: usize) -> Option<u8> {
+    let word: i32 = i32_data[index / 3];
+    if word < 0 {
+        return None;
+    }
+    access_value(
+        word as u64,
+        byte_position(bytes_in_word(word), index % 3),
+        3,
+    )
+    .try_into()
+    .ok()
+}
+
+// Panics on a word checked_byte_at rejects
+pub(crate) fn byte_at(i32_data: &[i32], index: usize) -> u8 {
+    checked_byte_at(i32_data, index)
+        .unwrap_or_else(|| panic!("word {} can not be decoded", index / 3))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EncodedView<'a> {
+    i32_data: &'a [i32],
+}
+
+impl<'a> EncodedView<'a> {
+    // The words must be well formed i.e. as produced by serialize_u8_to_i32 or held by EncodedBytes. get returns
+    // None for a byte in a malformed word, range and iter panic on one
+    pub fn new(i32_data: &'a [i32]) -> EncodedView<'a> {
+        EncodedView { i32_data }
+    }
+
+    pub fn words(&self) -> &'a [i32] {
+        self.i32_data
+    }
+
+    pub fn len(&self) -> usize {
+        encoded_len(self.i32_data)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.i32_data.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<u8> {
+        if index < self.len() {
+            checked_byte_at(self.i32_data, index)
+        } else {
+            None
+        }
+    }
+
+    // Decodes only the words which hold the requested bytes
+    pub fn range<R: RangeBounds<usize>>(&self, range: R) -> Vec<u8> {
+        let range: Range<usize> = resolve_range(range, self.len());
+        range.map(|index| byte_at(self.i32_data, index)).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + 'a {
+        let i32_data: &'a [i32] = self.i32_data;
+        (0..encoded_len(i32_data)).map(move |index| byte_at(i32_data, index))
+    }
+}
+
+pub(crate) fn set_byte_at(i32_data: &mut [i32], index: usize, value: u8) {
+    let word: i32 = i32_data[index / 3];
+    let position: u64 = byte_position(bytes_in_word(word), index % 3);
+    // Only the byte's own digit group changes, the mode digit (and so any tail mode) is left alone
+    i32_data[index / 3] = insert_value_at_position(word as u64, value.into(), position, 3)
+        .try_into()
+        .unwrap();
+}
+
+#[derive(Debug)]
+pub struct EncodedViewMut<'a> {
+    i32_data: &'a mut [i32],
+}
+
+impl<'a> EncodedViewMut<'a> {
+    // The words must be well formed i.e. as produced by serialize_u8_to_i32
+    pub fn new(i32_data: &'a mut [i32]) -> EncodedViewMut<'a> {
+        EncodedViewMut { i32_data }
+    }
+
+    pub fn as_view(&self) -> EncodedView<'_> {
+        EncodedView::new(self.i32_data)
+    }
+
+    pub fn len(&self) -> usize {
+        encoded_len(self.i32_data)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.i32_data.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<u8> {
+        self.as_view().get(index)
+    }
+
+    // Rewrites a single byte, panicking if the index is out of bounds
+    pub fn set(&mut self, index: usize, value: u8) {
+        let len: usize = self.len();
+        assert!(
+            index < len,
+            "index {} out of bounds for {} bytes",
+            index,
+            len
+        );
+        set_byte_at(self.i32_data, index, value);
+    }
+
+    // Applies the function to every byte, writing back only the bytes which change
+    pub fn map_in_place<F: FnMut(u8) -> u8>(&mut self, mut f: F) {
+        for index in 0..self.len() {
+            let value: u8 = byte_at(self.i32_data, index);
+            let new_value: u8 = f(value);
+            if new_value != value {
+                set_byte_at(self.i32_data, index, new_value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s_d_u8_i32;
+
+    #[test]
+    fn test_get() {
+        let v: Vec<i32> = vec![1009010011, 1012013014, 2000015016];
+        let view = EncodedView::new(&v);
+        assert_eq!(view.len(), 8);
+        assert_eq!(view.get(0), Some(9));
+        assert_eq!(view.get(5), Some(14));
+        assert_eq!(view.get(6), Some(15));
+        assert_eq!(view.get(7), Some(16));
+        assert_eq!(view.get(8), None);
+    }
+
+    #[test]
+    fn test_get_malformed_word() {
+        let v: Vec<i32> = vec![1009010011, 1999000000, 2000015016];
+        let view = EncodedView::new(&v);
+        assert_eq!(view.get(2), Some(11));
+        assert_eq!(view.get(3), None);
+        assert_eq!(view.get(4), Some(0));
+        assert_eq!(EncodedView::new(&[-5]).get(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "word 1 can not be decoded")]
+    fn test_range_malformed_word() {
+        EncodedView::new(&[1009010011, 1999000000]).range(..);
+    }
+
+    #[test]
+    fn test_get_single_byte_tail() {
+        let v: Vec<i32> = vec![1099100101, 1102103104, 105];
+        let view = EncodedView::new(&v);
+        assert_eq!(view.len(), 7);
+        assert_eq!(view.get(6), Some(105));
+        assert_eq!(view.get(7), None);
+    }
+
+    #[test]
+    fn test_range_matches_deserialize() {
+        for length in 0..12 {
+            let data: Vec<u8> = (0..length).map(|i| (i * 40) as u8).collect();
+            let v: Vec<i32> = s_d_u8_i32::serialize_u8_to_i32(data.clone());
+            let view = EncodedView::new(&v);
+            assert_eq!(view.len(), data.len());
+            assert_eq!(view.iter().collect::<Vec<u8>>(), data);
+            for start in 0..=length {
+                for end in start..=length {
+                    assert_eq!(view.range(start..end), data[start..end].to_vec());
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_range_out_of_bounds() {
+        let v: Vec<i32> = vec![1001002003];
+        EncodedView::new(&v).range(2..4);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut v: Vec<i32> = vec![1009010011, 1012013014, 2000015016];
+        let mut view = EncodedViewMut::new(&mut v);
+        view.set(1, 255);
+        view.set(7, 0);
+        assert_eq!(view.get(7), Some(0));
+        assert_eq!(v, vec![1009255011, 1012013014, 2000015000]);
+        let mut tail: Vec<i32> = vec![105];
+        EncodedViewMut::new(&mut tail).set(0, 7);
+        assert_eq!(tail, vec![7]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_out_of_bounds() {
+        let mut v: Vec<i32> = vec![2000015016];
+        EncodedViewMut::new(&mut v).set(2, 1);
+    }
+
+    #[test]
+    fn test_map_in_place_solarize() {
+        for length in 0..10 {
+            let pixels: Vec<u8> = (0..length).map(|i| (i * 29) as u8).collect();
+            let mut v: Vec<i32> = s_d_u8_i32::serialize_u8_to_i32(pixels.clone());
+            EncodedViewMut::new(&mut v).map_in_place(|pixel| {
+                if 200 - pixel as i32 > 0 {
+                    200 - pixel
+                } else {
+                    pixel
+                }
+            });
+            let expected: Vec<u8> = pixels
+                .iter()
+                .map(|pixel| if *pixel < 200 { 200 - pixel } else { *pixel })
+                .collect();
+            // The result is exactly what re-encoding the solarized pixels produces
+            assert_eq!(v, s_d_u8_i32::serialize_u8_to_i32(expected));
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let view = EncodedView::new(&[]);
+        assert!(view.is_empty());
+        assert_eq!(view.get(0), None);
+        assert_eq!(view.range(..), Vec::<u8>::new());
+    }
+}